@@ -0,0 +1,575 @@
+//! # Checkpointing and resuming long running Pop Jump Push enumerations.
+//!
+//! Every Pop Jump Push worker is fully described by its current `sequence_indices` stack and the
+//! `stop_index`/`stop_value` bounding its chunk (the sequential version is a single worker with a
+//! stop of `0`/`0`). A checkpoint stores, for each worker, the next ideal it would visit so a
+//! resumed run continues exactly where the saved one stopped without revisiting any ideal.
+//!
+//! Workers pause together whenever a checkpoint is due, the states are written to a json file
+//! (via a temporary file and a rename so an interruption never leaves a partial checkpoint) and
+//! the workers then carry on from where they paused.
+
+use rayon::prelude::*;
+use std::fmt;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::node_manipulation::Tree;
+use crate::node_manipulation::TreeError;
+use crate::pop_jump_push;
+use crate::pop_jump_push::OutputVisitor;
+use crate::pop_jump_push_par;
+use crate::visitor::IdealVisitor;
+use crate::workers::WorkerVisitor;
+
+/// The number of visits between checks of the pause conditions.
+const CHECK_INTERVAL: usize = 1 << 16;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    TreeMismatch,
+    NoWorkers,
+    InvalidIndices(usize),
+    InvalidWorkerId(usize),
+    Tree(TreeError),
+    ThreadPool(rayon::ThreadPoolBuildError),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "unable to access checkpoint file: {e}"),
+            CheckpointError::Json(e) => write!(f, "invalid checkpoint json: {e}"),
+            CheckpointError::TreeMismatch => {
+                write!(f, "the checkpoint was saved for a different tree")
+            }
+            CheckpointError::NoWorkers => write!(f, "the checkpoint has no worker states"),
+            CheckpointError::InvalidIndices(chunk) => write!(
+                f,
+                "worker state {chunk} isn't an ascending stack of the tree's pre-order indices"
+            ),
+            CheckpointError::InvalidWorkerId(id) => {
+                write!(f, "worker id {id} isn't below the number of worker states")
+            }
+            CheckpointError::Tree(e) => write!(f, "{e}"),
+            CheckpointError::ThreadPool(e) => write!(f, "unable to start the workers: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(e) => Some(e),
+            CheckpointError::Json(e) => Some(e),
+            CheckpointError::TreeMismatch => None,
+            CheckpointError::NoWorkers => None,
+            CheckpointError::InvalidIndices(_) => None,
+            CheckpointError::InvalidWorkerId(_) => None,
+            CheckpointError::Tree(e) => Some(e),
+            CheckpointError::ThreadPool(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for CheckpointError {
+    fn from(e: std::io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl From<rayon::ThreadPoolBuildError> for CheckpointError {
+    fn from(e: rayon::ThreadPoolBuildError) -> Self {
+        CheckpointError::ThreadPool(e)
+    }
+}
+
+impl From<TreeError> for CheckpointError {
+    fn from(e: TreeError) -> Self {
+        CheckpointError::Tree(e)
+    }
+}
+
+impl From<serde_json::Error> for CheckpointError {
+    fn from(e: serde_json::Error) -> Self {
+        CheckpointError::Json(e)
+    }
+}
+
+/// The resumable state of one worker chunk; an empty `sequence_indices` means it is done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkerState {
+    pub worker_id: usize,
+    pub sequence_indices: Vec<usize>,
+    pub stop_index: usize,
+    pub stop_value: usize,
+    pub visited: u128,
+}
+
+/// The pre-order tree the workers run on followed by each worker's state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub jump_indices: Vec<usize>,
+    pub labels: Vec<usize>,
+    pub workers: Vec<WorkerState>,
+}
+
+impl Checkpoint {
+    pub fn new(tree: &Tree, max_workers: usize) -> Result<Self, TreeError> {
+        /*!  - Returns the starting state using the sequential or parallel worker chunks. */
+        if max_workers < 2 {
+            let (_, sequence_indices, jump_indices, labels) = pop_jump_push::prep_args(tree, 0);
+            let worker = WorkerState {
+                worker_id: 0,
+                sequence_indices,
+                stop_index: 0,
+                stop_value: 0,
+                visited: 0,
+            };
+            return Ok(Checkpoint {
+                jump_indices,
+                labels,
+                workers: vec![worker],
+            });
+        }
+        let args = pop_jump_push_par::prep_args(tree, 0, max_workers)?;
+        let workers = args
+            .iter()
+            .map(|arg_set| WorkerState {
+                worker_id: arg_set.worker_id(),
+                sequence_indices: arg_set.sequence_indices().to_vec(),
+                stop_index: arg_set.stop_index(),
+                stop_value: arg_set.stop_value(),
+                visited: 0,
+            })
+            .collect();
+        Ok(Checkpoint {
+            jump_indices: args[0].jump_indices().to_vec(),
+            labels: args[0].labels().to_vec(),
+            workers,
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        /*!  - Returns true once every worker has visited all of its ideals. */
+        self.workers.iter().all(|w| w.sequence_indices.is_empty())
+    }
+
+    pub fn visited(&self) -> u128 {
+        /*!  - Returns the number of ideals visited by all workers so far. */
+        self.workers.iter().map(|w| w.visited).sum()
+    }
+
+    pub fn validate(&self) -> Result<(), CheckpointError> {
+        /*!  - Checks every worker state can be resumed on the checkpoint's own tree. */
+        // The chunks can't have been handed to more workers than there are chunks.
+        let num_nodes = self.jump_indices.len();
+        if self.workers.is_empty() {
+            return Err(CheckpointError::NoWorkers);
+        }
+        for (chunk, worker) in self.workers.iter().enumerate() {
+            let indices = &worker.sequence_indices;
+            let ascending = indices.windows(2).all(|pair| pair[0] < pair[1]);
+            if !ascending || indices.last().is_some_and(|&last| last >= num_nodes) {
+                return Err(CheckpointError::InvalidIndices(chunk));
+            }
+            if worker.worker_id >= self.workers.len() {
+                return Err(CheckpointError::InvalidWorkerId(worker.worker_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn read_checkpoint(path: &Path) -> Result<Checkpoint, CheckpointError> {
+    /*!  - Returns the checkpoint saved in a json file. */
+    let text = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+pub fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<(), CheckpointError> {
+    /*!  - Saves the checkpoint as json replacing the file only once it is fully written. */
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, serde_json::to_string(checkpoint)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+pub fn pop_jump_push_resumable<V: IdealVisitor>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<usize>,
+    jump_indices: &[usize],
+    stop_index: usize,
+    stop_value: usize,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm for a chunk, stepping past each visited ideal.
+
+    Unlike `pop_jump_push_par` the stack is advanced before acting on the visitor's result so on
+    `ControlFlow::Break` sequence_indices holds the next ideal to visit (empty once the chunk is
+    complete) and passing it back in resumes the enumeration.
+    */
+    while !sequence_indices.is_empty() {
+        let flow = visitor.visit(sequence_indices);
+        if sequence_indices.len() > stop_index && sequence_indices[stop_index] >= stop_value {
+            let index = jump_indices[sequence_indices.pop().unwrap()];
+            if index < num_nodes {
+                sequence_indices.extend(index..num_nodes);
+            }
+        } else {
+            sequence_indices.clear();
+        }
+        flow?;
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn visit_checkpointed<V, F>(
+    checkpoint: &mut Checkpoint,
+    path: &Path,
+    interval: Duration,
+    interrupted: &AtomicBool,
+    make_visitor: F,
+) -> Result<(), CheckpointError>
+where
+    V: IdealVisitor + Send,
+    F: Fn(&WorkerState) -> V + Sync,
+{
+    /*!  - Runs the unfinished workers saving a checkpoint every interval until done or interrupted.
+
+    Each worker runs its chunks in turn on its own thread of a pool with one thread per worker.
+    The checkpoint is saved one final time when every worker is done or interrupted is set.
+    */
+    let num_nodes = checkpoint.jump_indices.len();
+    let num_workers = checkpoint
+        .workers
+        .iter()
+        .map(|worker| worker.worker_id + 1)
+        .max()
+        .unwrap_or(1);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_workers)
+        .build()?;
+    loop {
+        let deadline = Instant::now() + interval;
+        let pause = AtomicBool::new(false);
+        let jump_indices = &checkpoint.jump_indices;
+        let mut worker_chunks = (0..num_workers).map(|_| vec![]).collect::<Vec<_>>();
+        for worker in checkpoint.workers.iter_mut() {
+            worker_chunks[worker.worker_id].push(worker);
+        }
+        pool.install(|| {
+            worker_chunks.par_iter_mut().for_each(|chunks| {
+                for worker in chunks.iter_mut() {
+                    if pause.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if worker.sequence_indices.is_empty() {
+                        continue;
+                    }
+                    let mut visitor = PausingVisitor {
+                        visitor: make_visitor(worker),
+                        pause: &pause,
+                        deadline,
+                        interrupted,
+                        count: 0,
+                    };
+                    let _ = pop_jump_push_resumable(
+                        num_nodes,
+                        &mut worker.sequence_indices,
+                        jump_indices,
+                        worker.stop_index,
+                        worker.stop_value,
+                        &mut visitor,
+                    );
+                    worker.visited += visitor.count as u128;
+                }
+            })
+        });
+        write_checkpoint(path, checkpoint)?;
+        if checkpoint.is_done() || interrupted.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+    }
+}
+
+/// Stops every worker after its current visit once the deadline passes or on interruption.
+struct PausingVisitor<'a, V> {
+    visitor: V,
+    pause: &'a AtomicBool,
+    deadline: Instant,
+    interrupted: &'a AtomicBool,
+    count: usize,
+}
+
+impl<V: IdealVisitor> IdealVisitor for PausingVisitor<'_, V> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[usize]) -> ControlFlow<()> {
+        // The ideal is always passed on since the worker steps past it either way.
+        let flow = self.visitor.visit(ideal);
+        self.count += 1;
+        if self.count.is_multiple_of(CHECK_INTERVAL)
+            && (self.interrupted.load(Ordering::Relaxed) || Instant::now() >= self.deadline)
+        {
+            self.pause.store(true, Ordering::Relaxed);
+        }
+        if flow.is_break() || self.pause.load(Ordering::Relaxed) {
+            self.pause.store(true, Ordering::Relaxed);
+            return ControlFlow::Break(());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+pub fn prep_checkpoint(
+    tree: &Tree,
+    resume_path: Option<&Path>,
+    max_workers: usize,
+    output: u8,
+) -> Result<Checkpoint, CheckpointError> {
+    /*!  -  Return the saved checkpoint when resuming, otherwise the starting one. */
+    let mut checkpoint = Checkpoint::new(tree, max_workers)?;
+    if let Some(resume_path) = resume_path {
+        let saved = read_checkpoint(resume_path)?;
+        if saved.jump_indices != checkpoint.jump_indices || saved.labels != checkpoint.labels {
+            return Err(CheckpointError::TreeMismatch);
+        }
+        saved.validate()?;
+        checkpoint = saved;
+    }
+    if output == 1 {
+        let arg = "jump_indices";
+        println!("{arg:>18}: {:?}", checkpoint.jump_indices);
+        println!("\t*** {:?} workers ***", checkpoint.workers.len());
+        for worker in checkpoint.workers.iter() {
+            let arg = ["Worker ", &worker.worker_id.to_string()].concat();
+            print!("{arg:>18}: ");
+            let arg = worker.stop_index;
+            print!("stop_index: {arg:>3}");
+            let arg = worker.stop_value;
+            print!(" stop_value: {arg:>3}");
+            let arg = worker.visited;
+            println!(" visited: {arg}");
+            let arg = "";
+            println!("{arg:>18}  indices: {:?} ", &worker.sequence_indices);
+        }
+    }
+    Ok(checkpoint)
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_checkpointed_main(
+    tree: &Tree,
+    path: &Path,
+    resume_path: Option<&Path>,
+    interval_secs: u64,
+    max_workers: usize,
+    output: u8,
+    interrupted: &AtomicBool,
+) -> Result<(), CheckpointError> {
+    /*! Processes the tree with 'visits', saving progress so an interrupted run can be resumed. */
+    let mut checkpoint = prep_checkpoint(tree, resume_path, max_workers, 0)?;
+    let num_nodes = tree.len();
+    let num_chunks = checkpoint.workers.len();
+    let previously_visited = checkpoint.visited();
+    print!("Generating ideals from {num_nodes} nodes using {num_chunks} chunks ");
+    println!("saving to {} every {interval_secs}s.", path.display());
+    if resume_path.is_some() {
+        println!("Resuming after {previously_visited} previously generated ideals.");
+    }
+    println!();
+    let interval = Duration::from_secs(interval_secs);
+    let labels = checkpoint.labels.clone();
+    let start_time = Instant::now();
+    if num_chunks == 1 {
+        visit_checkpointed(&mut checkpoint, path, interval, interrupted, |_| {
+            OutputVisitor {
+                labels: &labels,
+                output,
+            }
+        })?;
+    } else {
+        visit_checkpointed(&mut checkpoint, path, interval, interrupted, |worker| {
+            WorkerVisitor {
+                labels: &labels,
+                worker_id: worker.worker_id,
+                output,
+                count: 0,
+                format: pop_jump_push::format_ideal,
+            }
+        })?;
+    }
+    let time_delta = start_time.elapsed().as_secs_f64();
+    let ideals_count = checkpoint.visited() - previously_visited;
+
+    if checkpoint.is_done() {
+        println!("\tCompleted generating ideals...");
+    } else {
+        println!("\tInterrupted, resume with --resume {}", path.display());
+    }
+    println!("\tGenerated {ideals_count} ideals in {time_delta}");
+    println!("\t{} ns per ideal", time_delta / ideals_count as f64 * 1e9);
+    println!("\t{} ideals generated in total\n", checkpoint.visited());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Mutex;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::test_support::random_tree;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sequential_ideals;
+
+    fn test_trees() -> Vec<Tree> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut trees = sample_trees();
+        trees.push(random_tree(&mut rng, 24, false));
+        trees
+    }
+
+    #[test]
+    fn resumed_chunks_continue_where_they_stopped() {
+        for tree in test_trees() {
+            let checkpoint = Checkpoint::new(&tree, 1).unwrap();
+            let worker = &checkpoint.workers[0];
+            let mut sequence_indices = worker.sequence_indices.clone();
+            let mut ideals = vec![];
+            for stop_after in [1, 2, 5] {
+                let mut count = 0;
+                let _ = pop_jump_push_resumable(
+                    tree.len(),
+                    &mut sequence_indices,
+                    &checkpoint.jump_indices,
+                    worker.stop_index,
+                    worker.stop_value,
+                    &mut |ideal: &[usize]| {
+                        ideals.push(ideal.to_vec());
+                        count += 1;
+                        match count == stop_after {
+                            true => ControlFlow::Break(()),
+                            false => ControlFlow::Continue(()),
+                        }
+                    },
+                );
+            }
+            let _ = pop_jump_push_resumable(
+                tree.len(),
+                &mut sequence_indices,
+                &checkpoint.jump_indices,
+                worker.stop_index,
+                worker.stop_value,
+                &mut |ideal: &[usize]| {
+                    ideals.push(ideal.to_vec());
+                    ControlFlow::Continue(())
+                },
+            );
+            assert!(sequence_indices.is_empty());
+            assert_eq!(ideals, sequential_ideals(&tree), "{tree:?}");
+        }
+    }
+
+    fn visit_interrupted(tree: &Tree, max_workers: usize, name: &str) -> Vec<Vec<usize>> {
+        /*!  - Returns the ideals of a checkpointed run interrupted and resumed every few visits. */
+        let path = std::env::temp_dir().join(format!(
+            "popjumppush_{}_{name}_{max_workers}.json",
+            std::process::id()
+        ));
+        let mut checkpoint = Checkpoint::new(tree, max_workers).unwrap();
+        let expected = sequential_ideals(tree).len();
+        let stop_after = expected / 4 + 1;
+        let ideals = Mutex::new(vec![]);
+        let mut rounds = 0;
+        while !checkpoint.is_done() {
+            let interrupted = AtomicBool::new(false);
+            let count = AtomicUsize::new(0);
+            let interval = Duration::from_secs(3600);
+            let result = visit_checkpointed(&mut checkpoint, &path, interval, &interrupted, |_| {
+                |ideal: &[usize]| {
+                    ideals.lock().unwrap().push(ideal.to_vec());
+                    if count.fetch_add(1, Ordering::Relaxed) + 1 == stop_after {
+                        interrupted.store(true, Ordering::Relaxed);
+                        return ControlFlow::Break(());
+                    }
+                    ControlFlow::Continue(())
+                }
+            });
+            result.unwrap();
+            checkpoint = read_checkpoint(&path).unwrap();
+            rounds += 1;
+        }
+        fs::remove_file(&path).unwrap();
+        // Other workers may finish their chunks before they see the pause.
+        assert!(expected <= stop_after || rounds > 1 || max_workers > 1);
+        assert_eq!(checkpoint.visited(), expected as u128);
+        ideals.into_inner().unwrap()
+    }
+
+    #[test]
+    fn corrupt_worker_states_are_an_error() {
+        let tree = Tree::new(1, vec![0, 1, 1, 2], vec![1, 2, 3, 4]).unwrap();
+        let checkpoint = Checkpoint::new(&tree, 2).unwrap();
+        assert!(checkpoint.validate().is_ok());
+        let corrupt = |change: fn(&mut Checkpoint)| {
+            let mut checkpoint = checkpoint.clone();
+            change(&mut checkpoint);
+            checkpoint.validate()
+        };
+        let result = corrupt(|c| c.workers.clear());
+        assert!(matches!(result, Err(CheckpointError::NoWorkers)));
+        for indices in [vec![0, 4], vec![0, 2, 1], vec![0, 0]] {
+            let mut checkpoint = checkpoint.clone();
+            checkpoint.workers[1].sequence_indices = indices;
+            let result = checkpoint.validate();
+            assert!(matches!(result, Err(CheckpointError::InvalidIndices(1))));
+        }
+        let result = corrupt(|c| c.workers[0].worker_id = 9);
+        assert!(matches!(result, Err(CheckpointError::InvalidWorkerId(9))));
+        assert!(corrupt(|c| c.workers[0].sequence_indices.clear()).is_ok());
+    }
+
+    #[test]
+    fn resuming_a_corrupt_checkpoint_is_an_error() {
+        let tree = Tree::new(1, vec![0, 1, 1, 2], vec![1, 2, 3, 4]).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("popjumppush_{}_corrupt.json", std::process::id()));
+        let mut checkpoint = Checkpoint::new(&tree, 1).unwrap();
+        checkpoint.workers[0].sequence_indices = vec![0, 7];
+        write_checkpoint(&path, &checkpoint).unwrap();
+        let result = prep_checkpoint(&tree, Some(&path), 1, 0);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(CheckpointError::InvalidIndices(0))));
+    }
+
+    #[test]
+    fn resumed_runs_visit_every_ideal_once() {
+        for (index, tree) in test_trees().iter().enumerate() {
+            let ideals = visit_interrupted(tree, 1, &format!("sequential_{index}"));
+            let expected = sequential_ideals(tree);
+            assert_eq!(ideals, expected, "{tree:?}");
+            if tree.root() == 0 {
+                continue;
+            }
+            let mut expected = expected;
+            expected.sort();
+            for max_workers in [2, 4] {
+                let mut ideals = visit_interrupted(tree, max_workers, &format!("par_{index}"));
+                ideals.sort();
+                assert_eq!(ideals, expected, "{max_workers} workers for {tree:?}");
+            }
+        }
+    }
+}
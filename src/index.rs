@@ -0,0 +1,187 @@
+//! # Node index types for the generators' hot loops.
+//!
+//! The Pop Jump Push stack and jump indices only ever hold values in `0..=num_nodes` so any
+//! unsigned integer that fits `num_nodes` can store them. The narrower types move fewer bytes per
+//! pushed and popped node, which `pop_jump_push_main` takes advantage of by picking the smallest.
+//! The sequential `pop_jump_push` and the parallel `pop_jump_push_par` and `visit_split` are
+//! generic over `NodeIndex`; the checkpointed, weighted, budgeted, sized, complement, ranked and
+//! Koda Ruskey generators all run on `usize`.
+//!
+//! Trees of up to 64 or 128 nodes can instead hold a whole ideal in an `IdealMask`, with bit `i`
+//! set when pre-order index `i` is in the ideal.
+
+use std::fmt;
+use std::ops::BitXor;
+use std::ops::Range;
+
+pub trait NodeIndex: Copy + Ord + fmt::Debug + Send + Sync {
+    /// The largest `num_nodes` this type can index.
+    const MAX: usize;
+
+    /// Convert an index known to be at most `MAX`.
+    fn from_usize(index: usize) -> Self;
+
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! impl_node_index {
+    ($($t:ty),*) => {
+        $(
+            impl NodeIndex for $t {
+                const MAX: usize = <$t>::MAX as usize;
+
+                #[inline(always)]
+                fn from_usize(index: usize) -> Self {
+                    let max = <Self as NodeIndex>::MAX;
+                    debug_assert!(index <= max, "{index} doesn't fit {}", stringify!($t));
+                    index as $t
+                }
+
+                #[inline(always)]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_node_index!(u8, u16, u32, usize);
+
+/// The `NodeIndex` types, from the narrowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexWidth {
+    U8,
+    U16,
+    U32,
+    Usize,
+}
+
+impl IndexWidth {
+    pub fn fitting(num_nodes: usize) -> Self {
+        /*!  - Returns the narrowest `NodeIndex` type that can index num_nodes nodes. */
+        if num_nodes <= u8::MAX as usize {
+            IndexWidth::U8
+        } else if num_nodes <= u16::MAX as usize {
+            IndexWidth::U16
+        } else if num_nodes <= u32::MAX as usize {
+            IndexWidth::U32
+        } else {
+            IndexWidth::Usize
+        }
+    }
+}
+
+impl fmt::Display for IndexWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            IndexWidth::U8 => "u8",
+            IndexWidth::U16 => "u16",
+            IndexWidth::U32 => "u32",
+            IndexWidth::Usize => "usize",
+        };
+        write!(f, "{name}")
+    }
+}
+
+pub fn compact_indices<I: NodeIndex>(indices: &[usize]) -> Vec<I> {
+    /*!  - Returns the indices converted to `I`, which must fit the largest of them. */
+    indices.iter().map(|&index| I::from_usize(index)).collect()
+}
+
+pub trait IdealMask: Copy + Eq + BitXor<Output = Self> + fmt::LowerHex + Send + Sync {
+    /// The number of nodes this type can hold.
+    const BITS: usize;
+
+    const EMPTY: Self;
+
+    /// The mask of the indices in range, which must end at most at `BITS`.
+    fn range(range: Range<usize>) -> Self;
+
+    /// The largest index in a non-empty mask.
+    fn last_index(self) -> usize;
+}
+
+macro_rules! impl_ideal_mask {
+    ($($t:ty),*) => {
+        $(
+            impl IdealMask for $t {
+                const BITS: usize = <$t>::BITS as usize;
+
+                const EMPTY: Self = 0;
+
+                #[inline(always)]
+                fn range(range: Range<usize>) -> Self {
+                    if range.is_empty() {
+                        return 0;
+                    }
+                    let bits = <Self as IdealMask>::BITS;
+                    (<$t>::MAX >> (bits - range.end)) & (<$t>::MAX << range.start)
+                }
+
+                #[inline(always)]
+                fn last_index(self) -> usize {
+                    <Self as IdealMask>::BITS - 1 - self.leading_zeros() as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_ideal_mask!(u64, u128);
+
+pub fn ideal_mask<M: IdealMask>(indices: &[usize]) -> M {
+    /*!  - Returns the mask of the pre-order indices. */
+    indices
+        .iter()
+        .fold(M::EMPTY, |mask, &index| mask ^ M::range(index..index + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths_fit_their_node_counts() {
+        assert_eq!(IndexWidth::fitting(1), IndexWidth::U8);
+        assert_eq!(IndexWidth::fitting(255), IndexWidth::U8);
+        assert_eq!(IndexWidth::fitting(256), IndexWidth::U16);
+        assert_eq!(IndexWidth::fitting(65_536), IndexWidth::U32);
+        assert_eq!(IndexWidth::Usize.to_string(), "usize");
+    }
+
+    #[test]
+    fn compact_indices_round_trip() {
+        let indices = vec![0, 3, 255];
+        let compact = compact_indices::<u8>(&indices);
+        assert_eq!(compact, [0, 3, 255]);
+        assert_eq!(
+            compact.iter().map(|i| i.to_usize()).collect::<Vec<_>>(),
+            indices
+        );
+    }
+
+    fn check_mask_edges<M: IdealMask + fmt::Debug>(full: M, top: M) {
+        assert_eq!(M::range(0..M::BITS), full);
+        assert_eq!(M::range(M::BITS - 1..M::BITS), top);
+        assert_eq!(M::range(3..3), M::EMPTY);
+        assert_eq!(M::range(M::BITS..M::BITS), M::EMPTY);
+        assert_eq!(M::range(0..1).last_index(), 0);
+        assert_eq!(full.last_index(), M::BITS - 1);
+        assert_eq!(top.last_index(), M::BITS - 1);
+        assert_eq!(ideal_mask::<M>(&[0, M::BITS - 1]), M::range(0..1) ^ top);
+    }
+
+    #[test]
+    fn masks_reach_their_top_bits() {
+        check_mask_edges(u64::MAX, 1u64 << 63);
+        check_mask_edges(u128::MAX, 1u128 << 127);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "256 doesn't fit u8")]
+    fn oversized_indices_are_caught() {
+        let _ = u8::from_usize(256);
+    }
+}
@@ -3,30 +3,77 @@
 //! This module provides an implementation of the Koda-Ruskey Ideals of Forest Posets algorithm,
 //! also known as Knuth Algorithm K TAoCP 4A 7.2.1.1.
 
+use std::ops::ControlFlow;
 use std::time::Instant;
 
 use crate::node_manipulation::arrange_by_traversal_post_order;
+use crate::node_manipulation::arrange_by_traversal_pre_order;
 use crate::node_manipulation::count_subtrees;
 use crate::node_manipulation::group_indices_by_value;
+use crate::node_manipulation::index_by_value;
+use crate::node_manipulation::CountOverflowError;
+use crate::node_manipulation::Tree;
+use crate::visitor::IdealVisitor;
+use crate::visitor::ToggleIdealVisitor;
 
-fn koda_ruskey(
+pub fn koda_ruskey<V: IdealVisitor<[u8]>>(
     active_nodes: &mut [u8],
     focus_pointers: &mut [usize],
     left_child: &[usize],
     fringe_l: &mut [usize],
     fringe_r: &mut [usize],
-    labels: &[usize],
-    output: u8,
-) {
+    visitor: &mut V,
+) -> ControlFlow<()> {
     /*!  - Implements the Koda-Ruskey algorithm.
+
+    Returns `ControlFlow::Break` if the visitor stopped the enumeration.
      */
+    koda_ruskey_steps(
+        active_nodes,
+        focus_pointers,
+        left_child,
+        fringe_l,
+        fringe_r,
+        |ideal, _| visitor.visit(ideal),
+    )
+}
+
+pub fn koda_ruskey_toggles<V: ToggleIdealVisitor>(
+    active_nodes: &mut [u8],
+    focus_pointers: &mut [usize],
+    left_child: &[usize],
+    fringe_l: &mut [usize],
+    fringe_r: &mut [usize],
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Koda-Ruskey algorithm also passing the node each step toggles. */
+    koda_ruskey_steps(
+        active_nodes,
+        focus_pointers,
+        left_child,
+        fringe_l,
+        fringe_r,
+        |ideal, toggled| visitor.visit(ideal, toggled),
+    )
+}
+
+#[inline(always)]
+fn koda_ruskey_steps<F: FnMut(&[u8], usize) -> ControlFlow<()>>(
+    active_nodes: &mut [u8],
+    focus_pointers: &mut [usize],
+    left_child: &[usize],
+    fringe_l: &mut [usize],
+    fringe_r: &mut [usize],
+    mut visit: F,
+) -> ControlFlow<()> {
+    /*!  - Runs Koda-Ruskey calling visit with the `active_nodes` and the toggled index `p`. */
     loop {
         let mut q = fringe_l[0];
         let p = focus_pointers[q];
         focus_pointers[q] = q;
 
         if p == 0 {
-            return;
+            return ControlFlow::Continue(());
         }
 
         if active_nodes[p] == 0 {
@@ -49,55 +96,141 @@ fn koda_ruskey(
 
         focus_pointers[p] = focus_pointers[fringe_l[p]];
         focus_pointers[fringe_l[p]] = fringe_l[p];
-        visit(active_nodes, labels, output);
+        visit(active_nodes, p)?;
+    }
+}
+
+/// The `active_nodes`, `focus_pointers`, `left_child`, `fringe_l` and `fringe_r` arguments for
+/// calling koda_ruskey followed by the post-order node `labels`.
+pub type KodaRuskeyArgs = (
+    Vec<u8>,
+    Vec<usize>,
+    Vec<usize>,
+    Vec<usize>,
+    Vec<usize>,
+    Vec<usize>,
+);
+
+/// Prints ideals according to the cli `output` setting.
+#[doc(hidden)]
+pub struct OutputVisitor<'a> {
+    pub labels: &'a [usize],
+    pub output: u8,
+}
+
+impl IdealVisitor<[u8]> for OutputVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[u8]) -> ControlFlow<()> {
+        visit(ideal, self.labels, self.output);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Prints only the nodes removed (`-label`) and added (`+label`) since the previous ideal.
+///
+/// Koda-Ruskey is a Gray code so each step toggles exactly one node, which `koda_ruskey_toggles`
+/// passes along.
+#[doc(hidden)]
+pub struct DeltaVisitor<'a> {
+    pub labels: &'a [usize],
+}
+
+impl DeltaVisitor<'_> {
+    pub fn delta(&self, ideal: &[u8], toggled: usize) -> String {
+        /*!  - Returns the change made by toggling the `active_nodes` index toggled. */
+        let sign = if ideal[toggled] == 1 { '+' } else { '-' };
+        format!("{sign}{}", self.labels[toggled - 1])
+    }
+}
+
+impl ToggleIdealVisitor for DeltaVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[u8], toggled: usize) -> ControlFlow<()> {
+        println!("{}", self.delta(ideal, toggled));
+        ControlFlow::Continue(())
     }
 }
 
-pub(crate) fn visit(ideal: &[u8], labels: &[usize], output: u8) {
+/// Prints each ideal as its mask of pre-order indices, the cli `output == 6`.
+///
+/// `pre_order_bits` holds the bit of each post-order node so the masks match `pop_jump_push`.
+#[doc(hidden)]
+pub struct MaskVisitor {
+    pub pre_order_bits: Vec<u128>,
+}
+
+impl MaskVisitor {
+    pub fn new(tree: &Tree, labels: &[usize]) -> Self {
+        /*!  - Returns the visitor for the post-order labels of `prep_args`, at most 128 nodes. */
+        let (_, pre_order) =
+            arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
+        let pre_order_indices = index_by_value(&pre_order);
+        let pre_order_bits = labels
+            .iter()
+            .map(|label| 1 << pre_order_indices[label])
+            .collect();
+        MaskVisitor { pre_order_bits }
+    }
+}
+
+impl IdealVisitor<[u8]> for MaskVisitor {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[u8]) -> ControlFlow<()> {
+        let mask = ideal[1..]
+            .iter()
+            .zip(self.pre_order_bits.iter())
+            .filter(|(&state, _)| state == 1)
+            .fold(0u128, |mask, (_, &bit)| mask | bit);
+        println!("{mask:#x}");
+        ControlFlow::Continue(())
+    }
+}
+
+#[doc(hidden)]
+pub fn visit(ideal: &[u8], labels: &[usize], output: u8) {
     /*!  -  Process/output ideals.
      */
     // This just ensures the compiler doesn't optimize anything away during `output == 0` benchmarking.
     let ideal = std::hint::black_box(ideal);
 
-    if output == 2 {
-        println!("{ideal:?}")
-    } else if output >= 3 {
-        let active_indices = ideal
-            .iter()
-            .skip(1)
-            .enumerate()
-            .filter(|x| *x.1 == 1u8)
-            .map(|x| x.0)
-            .collect::<Vec<_>>();
-        if output == 3 {
-            println!("{active_indices:?}");
-        } else {
-            let mut result: Vec<_> = active_indices.iter().map(|i| labels[*i]).collect();
-            result.sort();
-            println!("{result:?}");
-        }
+    if output >= 2 {
+        println!("{}", format_ideal(ideal, labels, output));
     };
 }
 
-pub(crate) fn prep_args(
-    root: usize,
-    parents: &[usize],
-    children: &[usize],
-    output: u8,
-) -> (
-    Vec<u8>,
-    Vec<usize>,
-    Vec<usize>,
-    Vec<usize>,
-    Vec<usize>,
-    Vec<usize>,
-) {
-    let (parents, children) = arrange_by_traversal_post_order(root, parents, children);
+#[doc(hidden)]
+pub fn format_ideal(ideal: &[u8], labels: &[usize], output: u8) -> String {
+    /*!  - Returns the `active_nodes` for output 2, the active post-order indices for 3, otherwise
+    the sorted labels.
+    */
+    if output == 2 {
+        return format!("{ideal:?}");
+    }
+    let active_indices = ideal
+        .iter()
+        .skip(1)
+        .enumerate()
+        .filter(|x| *x.1 == 1u8)
+        .map(|x| x.0)
+        .collect::<Vec<_>>();
+    if output == 3 {
+        format!("{active_indices:?}")
+    } else {
+        let mut result: Vec<_> = active_indices.iter().map(|i| labels[*i]).collect();
+        result.sort();
+        format!("{result:?}")
+    }
+}
+
+pub fn prep_args(tree: &Tree, output: u8) -> KodaRuskeyArgs {
+    let (parents, children) =
+        arrange_by_traversal_post_order(tree.root(), tree.parents(), tree.children());
     let labels = children.clone();
-    let (root, parents, children) = sorted_post_order_indices(root, &parents, &children);
+    let (root, parents, children) = sorted_post_order_indices(tree.root(), &parents, &children);
 
     let left_child = leftmost_children_indices(root, &parents, &children);
-    let left_child = [parents.iter().position(|&x| x == root).unwrap() + 1]
+    let first = parents.iter().position(|&x| x == 0);
+    let left_child = [first.unwrap() + 1]
         .iter()
         .chain(left_child.iter())
         .cloned()
@@ -161,13 +294,14 @@ fn generate_sibling_arrays(parents: &[usize], children: &[usize]) -> (Vec<usize>
 fn sorted_post_order_indices(
     _root: usize,
     parents: &[usize],
-    children: &Vec<usize>,
+    children: &[usize],
 ) -> (usize, Vec<usize>, Vec<usize>) {
     /*!  - Returns sorted postorder children and parents.
      */
+    let label_indices = index_by_value(children);
     let post_parents = parents
         .iter()
-        .map(|p| children.iter().position(|&x| x == *p).unwrap_or(0))
+        .map(|p| label_indices.get(p).cloned().unwrap_or(0))
         .collect::<Vec<_>>();
     let post_parents = post_parents
         .into_iter()
@@ -185,37 +319,32 @@ fn sorted_post_order_indices(
 fn leftmost_children_indices(_root: usize, parents: &[usize], children: &[usize]) -> Vec<usize> {
     /*! Return an array indicating the index of the left child of each parent.
      */
+    let child_indices = group_indices_by_value(parents);
     children
         .iter()
-        .map(|&n| {
-            if parents.contains(&n) {
-                parents.iter().position(|&x| x == n).unwrap() + 1
-            } else {
-                0
-            }
+        .map(|n| match child_indices.get(n) {
+            Some(indices) => indices[0] + 1,
+            None => 0,
         })
         .collect()
 }
 
-pub(crate) fn koda_ruskey_main(
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
-    output: u8,
-    reps: u32,
-) {
+#[doc(hidden)]
+pub fn koda_ruskey_main(tree: &Tree, output: u8, reps: u32) -> Result<(), CountOverflowError> {
     /*! Rust doesn't have stable generators as of yet so this serves as the driver and the whole
      * tree gets processed with 'visits'.
      */
 
-    let ideals_count = count_subtrees(children[0], parents, children);
-    let num_nodes = children.len();
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
+    let num_nodes = tree.len();
     let ttl_ideals = ideals_count as f64 * reps as f64;
     println!(
         "Generating {ideals_count} ideals from {num_nodes} nodes {reps} times ({ttl_ideals}).\n"
     );
 
-    let args = prep_args(root, parents, children, output);
+    let args = prep_args(tree, output);
+    // The mask visitor maps every post-order node to its pre-order bit, so it is built untimed.
+    let mut mask_visitor = (output == 6).then(|| MaskVisitor::new(tree, &args.5));
     let start_time = Instant::now();
     let mut time_delta = f64::MAX;
     let mut i = 0;
@@ -224,15 +353,39 @@ pub(crate) fn koda_ruskey_main(
         let (mut active_nodes, mut focus_pointers, left_child, mut fringe_l, mut fringe_r, labels) =
             args.clone();
         let run_start_time = Instant::now();
-        koda_ruskey(
-            &mut active_nodes,
-            &mut focus_pointers,
-            &left_child,
-            &mut fringe_l,
-            &mut fringe_r,
-            &labels,
-            output,
-        );
+        if output == 5 {
+            let mut visitor = DeltaVisitor { labels: &labels };
+            let _ = koda_ruskey_toggles(
+                &mut active_nodes,
+                &mut focus_pointers,
+                &left_child,
+                &mut fringe_l,
+                &mut fringe_r,
+                &mut visitor,
+            );
+        } else if let Some(visitor) = mask_visitor.as_mut() {
+            let _ = koda_ruskey(
+                &mut active_nodes,
+                &mut focus_pointers,
+                &left_child,
+                &mut fringe_l,
+                &mut fringe_r,
+                visitor,
+            );
+        } else {
+            let mut visitor = OutputVisitor {
+                labels: &labels,
+                output,
+            };
+            let _ = koda_ruskey(
+                &mut active_nodes,
+                &mut focus_pointers,
+                &left_child,
+                &mut fringe_l,
+                &mut fringe_r,
+                &mut visitor,
+            );
+        }
         let run_time_delta = run_start_time.elapsed().as_secs_f64();
         time_delta = if time_delta < run_time_delta {
             time_delta
@@ -253,4 +406,104 @@ pub(crate) fn koda_ruskey_main(
         "\t{} ns best per ideal\n",
         time_delta / ideals_count as f64 * 1e9
     );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::replay_delta;
+    use crate::test_support::sample_trees;
+
+    /// Replays each `DeltaVisitor` line, checking it against the visited `active_nodes`.
+    struct ReplayVisitor<'a> {
+        delta_visitor: DeltaVisitor<'a>,
+        replayed: BTreeSet<usize>,
+        ideals: BTreeSet<Vec<usize>>,
+        count: usize,
+    }
+
+    impl ToggleIdealVisitor for ReplayVisitor<'_> {
+        fn visit(&mut self, ideal: &[u8], toggled: usize) -> ControlFlow<()> {
+            let line = self.delta_visitor.delta(ideal, toggled);
+            replay_delta(&mut self.replayed, &line);
+            let active_labels = ideal[1..]
+                .iter()
+                .zip(self.delta_visitor.labels)
+                .filter(|(&state, _)| state == 1)
+                .map(|(_, &label)| label)
+                .collect::<BTreeSet<_>>();
+            assert_eq!(self.replayed, active_labels);
+            self.ideals.insert(active_labels.into_iter().collect());
+            self.count += 1;
+            ControlFlow::Continue(())
+        }
+    }
+
+    #[test]
+    fn deltas_replay_to_each_ideal() {
+        for tree in sample_trees() {
+            let (
+                mut active_nodes,
+                mut focus_pointers,
+                left_child,
+                mut fringe_l,
+                mut fringe_r,
+                labels,
+            ) = prep_args(&tree, 0);
+            let mut visitor = ReplayVisitor {
+                delta_visitor: DeltaVisitor { labels: &labels },
+                replayed: BTreeSet::new(),
+                ideals: BTreeSet::new(),
+                count: 0,
+            };
+            let _ = koda_ruskey_toggles(
+                &mut active_nodes,
+                &mut focus_pointers,
+                &left_child,
+                &mut fringe_l,
+                &mut fringe_r,
+                &mut visitor,
+            );
+            assert_eq!(visitor.ideals, brute_force_ideals(&tree), "{tree:?}");
+            assert_eq!(visitor.count, visitor.ideals.len());
+        }
+    }
+
+    #[test]
+    fn breaking_stops_after_exactly_k_visits() {
+        for tree in sample_trees() {
+            let num_ideals = brute_force_ideals(&tree).len();
+            for k in [1, 2, num_ideals] {
+                let (
+                    mut active_nodes,
+                    mut focus_pointers,
+                    left_child,
+                    mut fringe_l,
+                    mut fringe_r,
+                    _,
+                ) = prep_args(&tree, 0);
+                let mut count = 0;
+                let flow = koda_ruskey(
+                    &mut active_nodes,
+                    &mut focus_pointers,
+                    &left_child,
+                    &mut fringe_l,
+                    &mut fringe_r,
+                    &mut |_: &[u8]| {
+                        count += 1;
+                        match count == k {
+                            true => ControlFlow::Break(()),
+                            false => ControlFlow::Continue(()),
+                        }
+                    },
+                );
+                assert_eq!(flow.is_break(), k <= num_ideals);
+                assert_eq!(count, k.min(num_ideals), "{k} of {tree:?}");
+            }
+        }
+    }
 }
@@ -0,0 +1,359 @@
+//! # Parallel implementation of the Koda-Ruskey Ideals of Forest Posets algorithm.
+//!
+//! The last nodes in post-order are the root followed by its nearest descendants (a reverse
+//! pre-order) so they form a top part of the tree containing the root. Each chunk fixes the state
+//! of every node in the top part to one of its ideals; the nodes left free are the whole subtrees
+//! hanging off the fixed active nodes. Koda-Ruskey then runs on that forest of free subtrees using
+//! the full tree's arrays, only relinking the fringe to the free subtree roots, so every chunk
+//! visits the same `active_nodes` representation as the sequential `koda_ruskey`.
+
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::time::Instant;
+
+use crate::koda_ruskey;
+use crate::koda_ruskey::koda_ruskey;
+use crate::node_manipulation::count_subtrees;
+use crate::node_manipulation::count_subtrees_at_each;
+use crate::node_manipulation::index_by_value;
+use crate::node_manipulation::Tree;
+use crate::node_manipulation::TreeError;
+use crate::timing::time_runs;
+use crate::visitor::IdealVisitor;
+use crate::workers::assign_workers;
+use crate::workers::count_workers;
+use crate::workers::report_workers;
+use crate::workers::WorkerVisitor;
+use crate::workers::CHUNKS_PER_WORKER;
+
+/// A chunk of the ideals and the worker it has been assigned to.
+pub struct KodaRuskeyParArg {
+    active_nodes: Vec<u8>,
+    focus_pointers: Vec<usize>,
+    left_child: Vec<usize>,
+    fringe_l: Vec<usize>,
+    fringe_r: Vec<usize>,
+    labels: Vec<usize>,
+    worker_id: usize,
+    predicted_count: u128,
+}
+
+impl KodaRuskeyParArg {
+    pub fn worker_id(&self) -> usize {
+        self.worker_id
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        /*!  - Returns the node labels indexed by post-order index. */
+        &self.labels
+    }
+
+    pub fn predicted_count(&self) -> u128 {
+        /*!  - Returns the number of ideals in the chunk. */
+        self.predicted_count
+    }
+}
+
+pub fn koda_ruskey_par<V: IdealVisitor<[u8]>>(
+    arg_set: &KodaRuskeyParArg,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Koda-Ruskey algorithm that works on chunks.
+
+    Koda-Ruskey never visits its starting state, the chunk's fixed nodes alone, so it is visited
+    before the loop.
+    */
+    let mut active_nodes = arg_set.active_nodes.clone();
+    let mut focus_pointers = arg_set.focus_pointers.clone();
+    let mut fringe_l = arg_set.fringe_l.clone();
+    let mut fringe_r = arg_set.fringe_r.clone();
+    visitor.visit(&active_nodes)?;
+    koda_ruskey(
+        &mut active_nodes,
+        &mut focus_pointers,
+        &arg_set.left_child,
+        &mut fringe_l,
+        &mut fringe_r,
+        visitor,
+    )
+}
+
+pub fn prep_args(
+    tree: &Tree,
+    output: u8,
+    max_workers: usize,
+) -> Result<Vec<KodaRuskeyParArg>, TreeError> {
+    /*!  -  Return the chunk arguments for koda_ruskey_par. */
+    // Every chunk's top part includes the last node in post-order, the root.
+    if tree.root() == 0 {
+        return Err(TreeError::Forest);
+    }
+    let (_, focus_pointers, left_child, fringe_l, fringe_r, labels) =
+        koda_ruskey::prep_args(tree, 0);
+
+    // Parents as post-order indices where 0 is the root's parent.
+    let label_indices: HashMap<usize, usize> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, &l)| (l, i + 1))
+        .collect();
+    let parent_labels: HashMap<usize, usize> = tree
+        .children()
+        .iter()
+        .cloned()
+        .zip(tree.parents().iter().cloned())
+        .collect();
+    let post_parents = std::iter::once(0)
+        .chain(
+            labels
+                .iter()
+                .map(|l| label_indices.get(&parent_labels[l]).cloned().unwrap_or(0)),
+        )
+        .collect::<Vec<_>>();
+
+    let num_chunks = max_workers.saturating_mul(CHUNKS_PER_WORKER);
+    let (top_min_index, top_states) = generate_top_states(&post_parents, num_chunks);
+
+    // Overflowing subtree counts saturate the predicted count.
+    let subtree_counts = count_subtrees_at_each(tree.parents(), tree.children());
+    let child_positions = index_by_value(tree.children());
+    let post_counts = labels
+        .iter()
+        .map(|l| subtree_counts[child_positions[l]].unwrap_or(u128::MAX))
+        .collect::<Vec<_>>();
+    let mut args = vec![];
+    for active_nodes in top_states {
+        // The free subtrees hang off of the active top nodes, ordered left to right.
+        let free_roots = (1..top_min_index)
+            .filter(|&i| post_parents[i] >= top_min_index && active_nodes[post_parents[i]] == 1)
+            .collect::<Vec<_>>();
+        let predicted_count = free_roots
+            .iter()
+            .fold(1u128, |count, &i| count.saturating_mul(post_counts[i - 1]));
+
+        let mut left_child = left_child.clone();
+        let mut fringe_l = fringe_l.clone();
+        let mut fringe_r = fringe_r.clone();
+        left_child[0] = free_roots.first().cloned().unwrap_or(0);
+        let mut previous = 0;
+        for &i in free_roots.iter() {
+            fringe_r[previous] = i;
+            fringe_l[i] = previous;
+            previous = i;
+        }
+        fringe_r[previous] = 0;
+        fringe_l[0] = previous;
+
+        args.push(KodaRuskeyParArg {
+            active_nodes,
+            focus_pointers: focus_pointers.clone(),
+            left_child,
+            fringe_l,
+            fringe_r,
+            labels: labels.clone(),
+            worker_id: 0,
+            predicted_count,
+        });
+    }
+    let predicted_counts = args.iter().map(|a| a.predicted_count).collect::<Vec<_>>();
+    let worker_ids = assign_workers(&predicted_counts, max_workers);
+    for (arg_set, worker_id) in args.iter_mut().zip(worker_ids) {
+        arg_set.worker_id = worker_id;
+    }
+
+    if output == 1 {
+        let arg = "top_min_index";
+        println!("{arg:>18}: {top_min_index}");
+        let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+        println!("\t*** {} chunks for {num_workers} workers ***", args.len());
+        for (chunk_id, arg_set) in args.iter().enumerate() {
+            let arg = ["Chunk ", &chunk_id.to_string()].concat();
+            print!("{arg:>18}: ");
+            let arg = arg_set.worker_id;
+            print!("worker: {arg:>3}");
+            let arg = arg_set.predicted_count;
+            println!(" predicted: {arg}");
+            let arg = "active_nodes";
+            println!("{arg:>18}: {:?}", arg_set.active_nodes);
+            let arg = "left_child";
+            println!("{arg:>18}: {:?}", arg_set.left_child);
+            let arg = "fringe_l";
+            println!("{arg:>18}: {:?}", arg_set.fringe_l);
+            let arg = "fringe_r";
+            println!("{arg:>18}: {:?}", arg_set.fringe_r);
+        }
+    }
+
+    Ok(args)
+}
+
+fn generate_top_states(post_parents: &[usize], num_chunks: usize) -> (usize, Vec<Vec<u8>>) {
+    /*!  - Returns the first post-order index of the top part and the top part's ideals.
+
+    Nodes are added to the top part in reverse post-order while its ideals, each containing the
+    root, number no more than num_chunks.
+    */
+    let n = post_parents.len() - 1;
+    let mut root_state = vec![0u8; n + 1];
+    root_state[n] = 1;
+    let mut top_states = vec![root_state];
+    let mut top_min_index = n;
+    while top_min_index > 1 {
+        let index = top_min_index - 1;
+        let mut next_states = vec![];
+        for state in top_states.iter() {
+            if state[post_parents[index]] == 1 {
+                let mut active_state = state.clone();
+                active_state[index] = 1;
+                next_states.push(active_state);
+            }
+            next_states.push(state.clone());
+        }
+        if next_states.len() > num_chunks {
+            break;
+        }
+        top_states = next_states;
+        top_min_index = index;
+    }
+    (top_min_index, top_states)
+}
+
+#[doc(hidden)]
+pub fn koda_ruskey_par_main(
+    tree: &Tree,
+    output: u8,
+    reps: u32,
+    max_workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    /*! Times reps runs of every chunk of `prep_args`, each worker running its chunks in turn. */
+    let args = prep_args(tree, output, max_workers)?;
+
+    let num_nodes = tree.len();
+    let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+    let mut worker_args = vec![vec![]; num_workers];
+    for arg_set in args.iter() {
+        worker_args[arg_set.worker_id].push(arg_set);
+    }
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
+    let ttl_ideals = ideals_count as f64 * reps as f64;
+    print!("Generating {ideals_count} ideals from {num_nodes} nodes using ");
+    println!(
+        "{num_workers} workers for {} chunks {reps} times ({ttl_ideals}).\n",
+        args.len()
+    );
+
+    let mut performance_data = vec![];
+    let timing = time_runs(
+        reps,
+        || (),
+        |()| {
+            performance_data = worker_args
+                .par_iter()
+                .enumerate()
+                .map(|(worker_id, chunks)| {
+                    let mut visitor = WorkerVisitor {
+                        labels: &args[0].labels,
+                        worker_id,
+                        output,
+                        count: 0,
+                        format: koda_ruskey::format_ideal,
+                    };
+                    let start_time = Instant::now();
+                    for arg_set in chunks.iter() {
+                        let _ = koda_ruskey_par(arg_set, &mut visitor);
+                    }
+                    let delta = start_time.elapsed();
+                    let predicted_count: u128 = chunks.iter().map(|c| c.predicted_count).sum();
+                    (worker_id, delta, visitor.count, predicted_count)
+                })
+                .collect();
+        },
+    );
+    timing.report(ideals_count, "ideals", "ideal");
+
+    report_workers(&performance_data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::test_support::random_tree;
+    use crate::test_support::sample_trees;
+
+    /// Collects the labels of the active nodes of each visited ideal.
+    struct CollectVisitor<'a> {
+        labels: &'a [usize],
+        ideals: Vec<Vec<usize>>,
+    }
+
+    impl IdealVisitor<[u8]> for CollectVisitor<'_> {
+        fn visit(&mut self, ideal: &[u8]) -> ControlFlow<()> {
+            let mut labels = ideal[1..]
+                .iter()
+                .zip(self.labels)
+                .filter(|(&state, _)| state == 1)
+                .map(|(_, &label)| label)
+                .collect::<Vec<_>>();
+            labels.sort();
+            self.ideals.push(labels);
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn sequential_ideals(tree: &Tree) -> Vec<Vec<usize>> {
+        let (mut active_nodes, mut focus_pointers, left_child, mut fringe_l, mut fringe_r, labels) =
+            koda_ruskey::prep_args(tree, 0);
+        let mut visitor = CollectVisitor {
+            labels: &labels,
+            ideals: vec![],
+        };
+        let _ = koda_ruskey(
+            &mut active_nodes,
+            &mut focus_pointers,
+            &left_child,
+            &mut fringe_l,
+            &mut fringe_r,
+            &mut visitor,
+        );
+        visitor.ideals
+    }
+
+    #[test]
+    fn chunks_visit_every_ideal_once() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut trees = sample_trees();
+        trees.push(random_tree(&mut rng, 24, false));
+        for tree in trees.iter().filter(|tree| tree.root() != 0) {
+            let mut expected = sequential_ideals(tree);
+            expected.sort();
+            for max_workers in [1, 2, 3, 8] {
+                let args = prep_args(tree, 0, max_workers).unwrap();
+                let mut ideals = vec![];
+                for arg_set in args.iter() {
+                    assert!(arg_set.worker_id() < max_workers);
+                    let mut visitor = CollectVisitor {
+                        labels: arg_set.labels(),
+                        ideals: vec![],
+                    };
+                    let _ = koda_ruskey_par(arg_set, &mut visitor);
+                    assert_eq!(visitor.ideals.len() as u128, arg_set.predicted_count());
+                    ideals.extend(visitor.ideals);
+                }
+                ideals.sort();
+                assert_eq!(ideals, expected, "{max_workers} workers for {tree:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn forests_have_no_chunks() {
+        for tree in sample_trees().iter().filter(|tree| tree.root() == 0) {
+            assert!(matches!(prep_args(tree, 0, 4), Err(TreeError::Forest)));
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! # Generating all ideals of an arborescence.
+//!
+//! Implementations of the Pop Jump Push algorithm (sequential, parallel and weighted) and the
+//! Koda-Ruskey Ideals of Forest Posets algorithm along with the tree preparation helpers they
+//! share.
+//!
+//! Trees are given as a `Tree`, validated from a `root` label and two equal length
+//! `parents`/`children` label vectors where the root's parent is `0`. See `sample_data` for
+//! examples. A `root` of `0` instead stands for the forest of every node whose parent is `0`,
+//! which the sequential generators support.
+//!
+//! The generators hand each ideal to an `IdealVisitor` which may stop the enumeration early by
+//! returning `ControlFlow::Break`; closures taking the ideal slice are visitors too. The `*_main`
+//! drivers and the printing visitors behind the `popjumppush` binary are hidden from these docs.
+//!
+//! ```
+//! use popjumppush::{IdealIterator, Tree};
+//!
+//! let parents = vec![0, 1, 1, 1, 2, 2, 3];
+//! let children = vec![1, 2, 3, 4, 5, 6, 7];
+//! let tree = Tree::new(1, parents, children).unwrap();
+//! let mut ideals = IdealIterator::new(&tree);
+//! let mut count = 0;
+//! while let Some(ideal) = ideals.next_ideal() {
+//!     assert_eq!(ideal[0], 0);
+//!     count += 1;
+//! }
+//! assert_eq!(count, 30);
+//! ```
+
+pub mod checkpoint;
+pub mod index;
+pub mod koda_ruskey;
+pub mod koda_ruskey_par;
+pub mod node_manipulation;
+pub mod pop_jump_push;
+pub mod pop_jump_push_par;
+pub mod pop_jump_push_weighted;
+pub mod rank;
+pub mod sample_data;
+pub mod sampler;
+mod timing;
+pub mod tree_file;
+pub mod visitor;
+mod workers;
+
+#[cfg(test)]
+mod test_support;
+
+pub use node_manipulation::LabelledTree;
+pub use node_manipulation::Tree;
+pub use node_manipulation::TreeError;
+pub use pop_jump_push::IdealIterator;
+pub use rank::IdealRanker;
+pub use sampler::IdealSampler;
+pub use visitor::IdealCounter;
+pub use visitor::IdealVisitor;
+pub use visitor::IncrementalIdealVisitor;
+pub use visitor::LabelledVisitor;
+pub use visitor::ToggleIdealVisitor;
+pub use visitor::WeightedIdealVisitor;
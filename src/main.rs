@@ -3,21 +3,43 @@ use mimalloc::MiMalloc;
 static GLOBAL: MiMalloc = MiMalloc;
 
 use clap::Parser;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-mod koda_ruskey;
-mod node_manipulation;
-mod pop_jump_push;
-mod pop_jump_push_par;
-mod sample_data;
+use popjumppush::koda_ruskey;
+use popjumppush::koda_ruskey_par;
+use popjumppush::pop_jump_push;
+use popjumppush::pop_jump_push_par;
+use popjumppush::pop_jump_push_weighted;
 
-use koda_ruskey::koda_ruskey_main;
-use node_manipulation::arrange_largest_subtrees;
-use pop_jump_push::pop_jump_push_main;
-use pop_jump_push_par::pop_jump_push_par_main;
-use sample_data::get_sample_data;
+use popjumppush::checkpoint::pop_jump_push_checkpointed_main;
+use popjumppush::checkpoint::prep_checkpoint;
+use popjumppush::koda_ruskey::koda_ruskey_main;
+use popjumppush::koda_ruskey_par::koda_ruskey_par_main;
+use popjumppush::node_manipulation::arrange_largest_subtrees;
+use popjumppush::node_manipulation::count_subtrees_by_size;
+use popjumppush::node_manipulation::Tree;
+use popjumppush::pop_jump_push::pop_jump_push_complement_main;
+use popjumppush::pop_jump_push::pop_jump_push_main;
+use popjumppush::pop_jump_push::pop_jump_push_shard_main;
+use popjumppush::pop_jump_push::pop_jump_push_sized_main;
+use popjumppush::pop_jump_push_par::pop_jump_push_par_main;
+use popjumppush::pop_jump_push_par::pop_jump_push_par_ordered_main;
+use popjumppush::pop_jump_push_par::pop_jump_push_split_main;
+use popjumppush::pop_jump_push_weighted::pop_jump_push_budgeted_main;
+use popjumppush::rank::shard_range;
+use popjumppush::rank::IdealRanker;
+use popjumppush::sample_data::get_sample_data;
+use popjumppush::sampler::sample_ideals_main;
+use popjumppush::sampler::IdealSampler;
+use popjumppush::tree_file::read_weighted_tree_file;
 
 pub(crate) type BoxedErr = Box<dyn std::error::Error>;
 
+/// Set by Ctrl-C so checkpointed runs save their progress before exiting.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 /// This is a small app for exploring and comparing an implementation of
 /// the Pop Jump Push algorithm and the Koda Ruskey Ideals from Forest
 /// Posets Algorithm (As described in Knuth's TAOCP Volume 4A 7.2.11).
@@ -31,14 +53,34 @@ struct Cli {
     #[arg(short, long, verbatim_doc_comment)]
     algo: u8,
 
-    #[arg(short, long, help = "See the sample_data.rs file for names.")]
-    sample_set: String,
+    #[arg(
+        short,
+        long,
+        required_unless_present = "input",
+        help = "See the sample_data.rs file for names."
+    )]
+    sample_set: Option<String>,
+
+    /// Read the tree from a file instead of using a sample set.
+    /// Either a json `{root, parents, children, weights?}` object or
+    /// one `parent child [weight]` edge per line.
+    /// Several `0 root` edges (or a json root of 0) give a forest, which
+    /// only the generators without workers support.
+    #[arg(short, long, conflicts_with = "sample_set", verbatim_doc_comment)]
+    input: Option<PathBuf>,
 
     /// 0 = performance timing
+    ///     (sequential Pop Jump Push also reports the speedup over usize indices;
+    ///     only it and the worker modes use the smaller index types, every other
+    ///     mode and Koda Ruskey always run on usize)
     /// 1 = algorithm arguments dump
     /// 2 = raw algorithm output
     /// 3 = ideals node output in native algorithm order
     /// 4 = ideals node output from native to sorted preorder (for comparing output)
+    /// 5 = only the nodes added (+) and removed (-) since the previous ideal
+    ///     (without workers; other modes print the ideals as with 4)
+    /// 6 = each ideal as a hex bit mask of its pre-order indices (at most 128 nodes)
+    ///     (without workers; other modes print the ideals as with 4)
     #[arg(short, long, verbatim_doc_comment)]
     output: u8,
 
@@ -47,92 +89,297 @@ struct Cli {
     #[arg(short, long, verbatim_doc_comment)]
     reps: u32,
 
-    /// The max number of workers to use. This only applies to Pop Jump Push.
+    /// The max number of workers to use.
     /// ( 0 or 1 is the same as omitting)
     #[arg(short, long, verbatim_doc_comment)]
-    workers: Option<u8>,
+    workers: Option<usize>,
+
+    /// Split chunks on demand so idle workers can always steal work instead
+    /// of handing each worker a fixed prefix chunk.
+    #[arg(
+        long,
+        requires = "workers",
+        conflicts_with_all = ["checkpoint", "resume"],
+        verbatim_doc_comment
+    )]
+    dynamic: bool,
+
+    /// Write the ideals in the sequential order instead of as each worker
+    /// generates them (output 2+ then matches the run without workers).
+    /// This only applies to Pop Jump Push.
+    #[arg(
+        long,
+        requires = "workers",
+        conflicts_with_all = ["dynamic", "checkpoint", "resume"],
+        verbatim_doc_comment
+    )]
+    ordered: bool,
 
     /// The arrange tree by size of subtrees.
     /// (true = largest rightmost, false = largest leftmost)
     #[arg(long, verbatim_doc_comment)]
     arrange_right: Option<bool>,
+
+    /// Only generate ideals whose total weight is at most this budget.
+    /// Weights come from the input file (default 1 per node) and must
+    /// be non-negative. This only applies to Pop Jump Push.
+    #[arg(long, conflicts_with = "workers", verbatim_doc_comment)]
+    max_weight: Option<i64>,
+
+    /// Only generate ideals with at least this many nodes.
+    /// This only applies to Pop Jump Push.
+    #[arg(long, conflicts_with_all = ["workers", "max_weight"], verbatim_doc_comment)]
+    min_size: Option<usize>,
+
+    /// Only generate ideals with at most this many nodes.
+    /// (use the same min and max size for exactly k nodes)
+    #[arg(long, conflicts_with_all = ["workers", "max_weight"], verbatim_doc_comment)]
+    max_size: Option<usize>,
+
+    /// Draw this many uniformly random ideals instead of generating all of them.
+    /// This only applies to Pop Jump Push.
+    #[arg(
+        long,
+        conflicts_with_all = ["workers", "max_weight", "min_size", "max_size"],
+        verbatim_doc_comment
+    )]
+    sample: Option<usize>,
+
+    /// Seed for the --sample random number generator.
+    /// (omit for a random seed)
+    #[arg(long, requires = "sample", verbatim_doc_comment)]
+    seed: Option<u64>,
+
+    /// Only generate shard i of N (0 <= i < N) of the Pop Jump Push order.
+    /// Shards cover contiguous ranks so their outputs concatenated in
+    /// order i = 0..N match the unsharded output.
+    #[arg(
+        long,
+        value_parser = parse_shard,
+        conflicts_with_all = ["workers", "max_weight", "min_size", "max_size", "sample"],
+        verbatim_doc_comment
+    )]
+    shard: Option<(u128, u128)>,
+
+    /// Generate the complement (the excluded nodes) of each ideal instead.
+    /// This only applies to Pop Jump Push.
+    #[arg(
+        long,
+        conflicts_with_all = ["workers", "max_weight", "min_size", "max_size", "sample", "shard"],
+        verbatim_doc_comment
+    )]
+    complement: bool,
+
+    /// Generate the order filters (up-sets), the complements plus the
+    /// set of every node. This only applies to Pop Jump Push.
+    #[arg(
+        long,
+        conflicts_with_all = ["workers", "max_weight", "min_size", "max_size", "sample", "shard", "complement"],
+        verbatim_doc_comment
+    )]
+    filters: bool,
+
+    /// Save progress to this file every --checkpoint-secs and on Ctrl-C.
+    /// This only applies to Pop Jump Push, with or without workers.
+    #[arg(
+        long,
+        conflicts_with_all = ["max_weight", "min_size", "max_size", "sample", "shard", "complement", "filters"],
+        verbatim_doc_comment
+    )]
+    checkpoint: Option<PathBuf>,
+
+    /// The number of seconds between checkpoints.
+    #[arg(long, default_value_t = 60, verbatim_doc_comment)]
+    checkpoint_secs: u64,
+
+    /// Continue the run saved in this checkpoint file without revisiting ideals.
+    /// Progress keeps being saved to it unless --checkpoint names another file.
+    /// (the workers are taken from the checkpoint)
+    #[arg(
+        long,
+        conflicts_with_all = ["workers", "max_weight", "min_size", "max_size", "sample", "shard", "complement", "filters"],
+        verbatim_doc_comment
+    )]
+    resume: Option<PathBuf>,
 }
 
-fn dump_args(
-    algos: Vec<&str>,
-    root: usize,
-    parents: &Vec<usize>,
-    children: &Vec<usize>,
-    max_workers: u8,
-) {
+fn parse_shard(arg: &str) -> Result<(u128, u128), String> {
+    let (shard, num_shards) = arg
+        .split_once('/')
+        .ok_or_else(|| format!("expected i/N but found {arg:?}"))?;
+    let shard = shard.trim().parse::<u128>().map_err(|e| e.to_string())?;
+    let num_shards = num_shards
+        .trim()
+        .parse::<u128>()
+        .map_err(|e| e.to_string())?;
+    if shard >= num_shards {
+        return Err(format!("shard {shard} must be less than {num_shards}"));
+    }
+    Ok((shard, num_shards))
+}
+
+/// The Pop Jump Push implementation selected by the cli options.
+enum Variant<'a> {
+    Sequential,
+    Parallel(usize),
+    Dynamic(usize),
+    Ordered(usize),
+    Budgeted(&'a [i64], i64),
+    Sized(usize, usize),
+    Sampled(usize, Option<u64>),
+    Sharded(u128, u128),
+    Complement(bool),
+    Checkpointed(&'a Path, Option<&'a Path>, u64, usize),
+}
+
+fn dump_args(algos: Vec<&str>, tree: &Tree, variant: &Variant) -> Result<(), BoxedErr> {
     println!("\n=== Test Set Data ===");
     let arg = "root";
-    println!("{arg:>18}: {root}");
+    println!("{arg:>18}: {}", tree.root());
     let arg = "parents";
-    println!("{arg:>18}: {parents:?}");
+    println!("{arg:>18}: {:?}", tree.parents());
     let arg = "children";
-    println!("{arg:>18}: {children:?}\n");
+    println!("{arg:>18}: {:?}\n", tree.children());
 
     for algo in algos.into_iter() {
         println!("=== {algo} ===");
         if algo == "pop_jump_push" {
-            if max_workers < 2 {
-                let _ = pop_jump_push::prep_args(root, parents, children, 1);
-            } else {
-                let _ = pop_jump_push_par::prep_args(root, parents, children, 1, max_workers);
+            match *variant {
+                Variant::Sequential => {
+                    let _ = pop_jump_push::prep_args(tree, 1);
+                }
+                Variant::Parallel(max_workers) | Variant::Ordered(max_workers) => {
+                    let _ = pop_jump_push_par::prep_args(tree, 1, max_workers)?;
+                }
+                Variant::Dynamic(_) => {
+                    let _ = pop_jump_push::prep_args(tree, 1);
+                }
+                Variant::Budgeted(weights, _) => {
+                    let _ = pop_jump_push_weighted::prep_args(tree, weights, 1);
+                }
+                Variant::Sized(_, _) => {
+                    let _ = pop_jump_push::prep_args(tree, 1);
+                    let size_counts =
+                        count_subtrees_by_size(tree.root(), tree.parents(), tree.children())?;
+                    let arg = "size_counts";
+                    println!("{arg:>18}: {size_counts:?}");
+                }
+                Variant::Sampled(_, seed) => {
+                    let sampler = IdealSampler::new(tree, seed)?;
+                    let arg = "jump_indices";
+                    println!("{arg:>18}: {:?}", sampler.jump_indices());
+                    let arg = "subtree_counts";
+                    println!("{arg:>18}: {:?}", sampler.subtree_counts());
+                }
+                Variant::Sharded(shard, num_shards) => {
+                    let _ = pop_jump_push::prep_args(tree, 1);
+                    let ranker = IdealRanker::new(tree)?;
+                    let arg = "suffix_counts";
+                    println!("{arg:>18}: {:?}", ranker.suffix_counts());
+                    let (start, end) = shard_range(ranker.count(), shard, num_shards);
+                    let arg = "ranks";
+                    println!("{arg:>18}: {start}..{end}");
+                }
+                Variant::Complement(_) => {
+                    let _ = pop_jump_push::prep_args(tree, 1);
+                }
+                Variant::Checkpointed(_, resume_path, _, max_workers) => {
+                    let _ = prep_checkpoint(tree, resume_path, max_workers, 1)?;
+                }
             }
+        } else if let Variant::Parallel(max_workers) | Variant::Dynamic(max_workers) = *variant {
+            let _ = koda_ruskey_par::prep_args(tree, 1, max_workers)?;
         } else {
-            let _ = koda_ruskey::prep_args(root, parents, children, 1);
+            let _ = koda_ruskey::prep_args(tree, 1);
         }
         println!()
     }
+    Ok(())
 }
 
-fn benchmark(
-    algos: Vec<&str>,
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
-    reps: u32,
-    max_workers: u8,
-) {
+fn benchmark(algos: Vec<&str>, tree: &Tree, reps: u32, variant: &Variant) -> Result<(), BoxedErr> {
     for algo in algos.into_iter() {
         println!("=== {algo} ===");
         if algo == "pop_jump_push" {
-            if max_workers < 2 {
-                pop_jump_push_main(root, parents, children, 0, reps);
-            } else {
-                pop_jump_push_par_main(root, parents, children, 0, reps, max_workers);
-            }
+            run_pop_jump_push(tree, 0, reps, variant)?;
         } else {
-            koda_ruskey_main(root, parents, children, 0, reps);
+            run_koda_ruskey(tree, 0, reps, variant)?;
         }
         println!()
     }
+    Ok(())
 }
 
 fn generate_ideals(
     algos: Vec<&str>,
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
+    tree: &Tree,
     output: u8,
-    max_workers: u8,
-) {
+    variant: &Variant,
+) -> Result<(), BoxedErr> {
     for algo in algos.into_iter() {
         println!("=== {algo} ===");
         if algo == "pop_jump_push" {
-            if max_workers < 2 {
-                pop_jump_push_main(root, parents, children, output, 1);
-            } else {
-                pop_jump_push_par_main(root, parents, children, output, 1, max_workers);
-            }
+            run_pop_jump_push(tree, output, 1, variant)?;
         } else {
-            koda_ruskey_main(root, parents, children, output, 1);
+            run_koda_ruskey(tree, output, 1, variant)?;
         }
         println!()
     }
+    Ok(())
+}
+
+fn run_pop_jump_push(
+    tree: &Tree,
+    output: u8,
+    reps: u32,
+    variant: &Variant,
+) -> Result<(), BoxedErr> {
+    match *variant {
+        Variant::Sequential => pop_jump_push_main(tree, output, reps)?,
+        Variant::Parallel(max_workers) => pop_jump_push_par_main(tree, output, reps, max_workers)?,
+        Variant::Dynamic(max_workers) => pop_jump_push_split_main(tree, output, reps, max_workers)?,
+        Variant::Ordered(max_workers) => {
+            pop_jump_push_par_ordered_main(tree, output, reps, max_workers)?
+        }
+        Variant::Budgeted(weights, budget) => {
+            pop_jump_push_budgeted_main(tree, weights, budget, output, reps)
+        }
+        Variant::Sized(min_size, max_size) => {
+            pop_jump_push_sized_main(tree, min_size, max_size, output, reps)?
+        }
+        Variant::Sampled(num_samples, seed) => {
+            sample_ideals_main(tree, num_samples, seed, output, reps)?
+        }
+        Variant::Sharded(shard, num_shards) => {
+            pop_jump_push_shard_main(tree, shard, num_shards, output, reps)?
+        }
+        Variant::Complement(filters) => pop_jump_push_complement_main(tree, filters, output, reps)?,
+        Variant::Checkpointed(path, resume_path, interval_secs, max_workers) => {
+            pop_jump_push_checkpointed_main(
+                tree,
+                path,
+                resume_path,
+                interval_secs,
+                max_workers,
+                output,
+                &INTERRUPTED,
+            )?
+        }
+    }
+    Ok(())
 }
+
+fn run_koda_ruskey(tree: &Tree, output: u8, reps: u32, variant: &Variant) -> Result<(), BoxedErr> {
+    // Koda Ruskey only has a parallel counterpart to the Pop Jump Push variants.
+    match *variant {
+        Variant::Parallel(max_workers) | Variant::Dynamic(max_workers) => {
+            koda_ruskey_par_main(tree, output, reps, max_workers)?
+        }
+        _ => koda_ruskey_main(tree, output, reps)?,
+    }
+    Ok(())
+}
+
 fn main() -> Result<(), BoxedErr> {
     let args = Cli::parse();
 
@@ -141,23 +388,87 @@ fn main() -> Result<(), BoxedErr> {
         1 => vec!["koda_ruskey"],
         _ => vec!["pop_jump_push", "koda_ruskey"],
     };
-    let (root, mut parents, mut children) = get_sample_data(&args.sample_set);
+    let (mut tree, weights) = match (&args.input, &args.sample_set) {
+        (Some(path), _) => read_weighted_tree_file(path)
+            .map_err(|e| format!("Unable to load tree from {}: {e}", path.display()))?,
+        (_, Some(sample_set)) => {
+            let (root, parents, children) = get_sample_data(sample_set)
+                .ok_or_else(|| format!("Sample set {sample_set:?} not found"))?;
+            let tree = Tree::new(root, parents, children)
+                .map_err(|e| format!("Invalid sample set {sample_set}: {e}"))?;
+            (tree, None)
+        }
+        _ => unreachable!("clap requires one of sample_set or input"),
+    };
+    let mut weights = weights.unwrap_or_else(|| vec![1; tree.len()]);
     if let Some(arrangement) = args.arrange_right {
-        (parents, children) = arrange_largest_subtrees(root, &parents, &children, arrangement);
+        let label_weights: HashMap<usize, i64> = tree
+            .children()
+            .iter()
+            .cloned()
+            .zip(weights.iter().cloned())
+            .collect();
+        let (parents, children) =
+            arrange_largest_subtrees(tree.root(), tree.parents(), tree.children(), arrangement);
+        weights = children.iter().map(|c| label_weights[c]).collect();
+        tree = Tree::new(tree.root(), parents, children)?;
     }
 
     let output = args.output;
     let reps = args.reps;
-    let max_workers = match args.workers {
-        Some(x) => x,
-        _ => 1,
+    let max_workers = args.workers.unwrap_or(1);
+    let variant = if let Some(budget) = args.max_weight {
+        if let Some(w) = weights.iter().find(|&&w| w < 0) {
+            return Err(format!("--max-weight requires non-negative weights but found {w}").into());
+        }
+        Variant::Budgeted(&weights, budget)
+    } else if let Some(num_samples) = args.sample {
+        Variant::Sampled(num_samples, args.seed)
+    } else if let Some((shard, num_shards)) = args.shard {
+        Variant::Sharded(shard, num_shards)
+    } else if args.min_size.is_some() || args.max_size.is_some() {
+        let min_size = args.min_size.unwrap_or(1);
+        let max_size = args.max_size.unwrap_or(tree.len());
+        Variant::Sized(min_size, max_size)
+    } else if args.complement || args.filters {
+        Variant::Complement(args.filters)
+    } else if let Some(path) = args.checkpoint.as_deref().or(args.resume.as_deref()) {
+        ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::Relaxed))?;
+        Variant::Checkpointed(
+            path,
+            args.resume.as_deref(),
+            args.checkpoint_secs,
+            max_workers,
+        )
+    } else if max_workers < 2 {
+        Variant::Sequential
+    } else if args.dynamic {
+        Variant::Dynamic(max_workers)
+    } else if args.ordered {
+        Variant::Ordered(max_workers)
+    } else {
+        Variant::Parallel(max_workers)
     };
 
-    match output {
-        0 => benchmark(algos, root, &parents, &children, reps, max_workers),
-        1 => dump_args(algos, root, &parents, &children, max_workers),
-        _ => generate_ideals(algos, root, &parents, &children, output, max_workers),
+    // The forest of every parentless node (a `0` root) isn't split into chunks.
+    let forest_unsupported = match variant {
+        Variant::Parallel(_) | Variant::Dynamic(_) | Variant::Ordered(_) => true,
+        Variant::Checkpointed(_, _, _, max_workers) => max_workers > 1,
+        Variant::Sequential | Variant::Budgeted(_, _) | Variant::Sized(_, _) => false,
+        Variant::Sampled(_, _) | Variant::Sharded(_, _) | Variant::Complement(_) => false,
+    };
+    if tree.root() == 0 && forest_unsupported {
+        return Err("forests are only supported without --workers".into());
     }
 
-    Ok(())
+    if output == 6 && tree.len() > u128::BITS as usize {
+        return Err("bit mask output (6) is only supported for up to 128 nodes".into());
+    }
+
+    let result = match output {
+        0 => benchmark(algos, &tree, reps, &variant),
+        1 => dump_args(algos, &tree, &variant),
+        _ => generate_ideals(algos, &tree, output, &variant),
+    };
+    result.map_err(|e| format!("Unable to generate ideals: {e}").into())
 }
@@ -1,6 +1,275 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
 
-pub(crate) fn group_indices_by_value(values: &[usize]) -> HashMap<usize, Vec<usize>> {
+/// The number of subtrees rooted at `node` doesn't fit in a `u128`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountOverflowError {
+    pub node: usize,
+}
+
+impl fmt::Display for CountOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let node = self.node;
+        write!(
+            f,
+            "the number of subtrees rooted at node {node} exceeds u128::MAX"
+        )
+    }
+}
+
+impl std::error::Error for CountOverflowError {}
+
+/// Why a `root`, `parents` and `children` triple isn't a tree (or a forest for a `0` root).
+///
+/// The nodes are reported by their labels, `usize` for a `Tree` and `T` for a `LabelledTree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError<T = usize> {
+    Empty,
+    LengthMismatch {
+        parents: usize,
+        children: usize,
+    },
+    ZeroLabel,
+    DuplicateId(T),
+    MissingRoot(T),
+    RootHasParent(T),
+    NoRoot,
+    MultipleRoots(Vec<T>),
+    Orphan(T),
+    Cycle(Vec<T>),
+    /// A valid forest given to a generator that needs a single root.
+    Forest,
+}
+
+impl TreeError {
+    pub fn map_labels<T>(self, label: impl Fn(usize) -> T) -> TreeError<T> {
+        /*!  - Returns the same error reporting each node as label(node). */
+        match self {
+            TreeError::Empty => TreeError::Empty,
+            TreeError::LengthMismatch { parents, children } => {
+                TreeError::LengthMismatch { parents, children }
+            }
+            TreeError::ZeroLabel => TreeError::ZeroLabel,
+            TreeError::DuplicateId(c) => TreeError::DuplicateId(label(c)),
+            TreeError::MissingRoot(r) => TreeError::MissingRoot(label(r)),
+            TreeError::RootHasParent(r) => TreeError::RootHasParent(label(r)),
+            TreeError::NoRoot => TreeError::NoRoot,
+            TreeError::MultipleRoots(roots) => {
+                TreeError::MultipleRoots(roots.into_iter().map(label).collect())
+            }
+            TreeError::Orphan(p) => TreeError::Orphan(label(p)),
+            TreeError::Cycle(nodes) => TreeError::Cycle(nodes.into_iter().map(label).collect()),
+            TreeError::Forest => TreeError::Forest,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for TreeError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::Empty => write!(f, "the tree has no nodes"),
+            TreeError::LengthMismatch { parents, children } => write!(
+                f,
+                "{parents} parents given for {children} children; the lengths must match"
+            ),
+            TreeError::ZeroLabel => write!(f, "0 is reserved for the root's parent"),
+            TreeError::DuplicateId(c) => write!(f, "node {c:?} has more than one parent"),
+            TreeError::MissingRoot(r) => write!(f, "root {r:?} is not one of the children"),
+            TreeError::RootHasParent(r) => write!(f, "root {r:?} has a parent"),
+            TreeError::NoRoot => write!(f, "every node has a parent so there is no root"),
+            TreeError::MultipleRoots(roots) => write!(f, "multiple roots found: {roots:?}"),
+            TreeError::Orphan(p) => write!(f, "parent {p:?} is not one of the children"),
+            TreeError::Cycle(nodes) => {
+                write!(
+                    f,
+                    "nodes in a cycle, not reachable from the root: {nodes:?}"
+                )
+            }
+            TreeError::Forest => write!(f, "only trees, not forests (a 0 root), are supported"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for TreeError<T> {}
+
+/// A validated tree, or forest when the root is `0`, that every `prep_args` takes.
+///
+/// The labels are non-zero, each child appears once with a parent that is either `0` or one of
+/// the children and every node hangs off of the root (or, for a forest, a parentless node).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tree {
+    root: usize,
+    parents: Vec<usize>,
+    children: Vec<usize>,
+}
+
+impl Tree {
+    pub fn new(root: usize, parents: Vec<usize>, children: Vec<usize>) -> Result<Self, TreeError> {
+        /*!  - Returns the tree once parents and children are checked to describe it. */
+        if parents.len() != children.len() {
+            return Err(TreeError::LengthMismatch {
+                parents: parents.len(),
+                children: children.len(),
+            });
+        }
+        if children.is_empty() {
+            return Err(TreeError::Empty);
+        }
+        if children.contains(&0) {
+            return Err(TreeError::ZeroLabel);
+        }
+
+        let mut seen = HashSet::new();
+        for &c in children.iter() {
+            if !seen.insert(c) {
+                return Err(TreeError::DuplicateId(c));
+            }
+        }
+        let roots = children
+            .iter()
+            .zip(parents.iter())
+            .filter(|(_, &p)| p == 0)
+            .map(|(&c, _)| c)
+            .collect::<Vec<_>>();
+        if root == 0 {
+            if roots.is_empty() {
+                return Err(TreeError::NoRoot);
+            }
+        } else {
+            match children.iter().position(|&c| c == root) {
+                None => return Err(TreeError::MissingRoot(root)),
+                Some(i) if parents[i] != 0 => return Err(TreeError::RootHasParent(root)),
+                _ => (),
+            }
+            if roots != [root] {
+                return Err(TreeError::MultipleRoots(roots));
+            }
+        }
+        if let Some(&p) = parents.iter().find(|&&p| p != 0 && !seen.contains(&p)) {
+            return Err(TreeError::Orphan(p));
+        }
+
+        // Every node must hang off of a root; anything else is part of a cycle.
+        let child_indices = group_indices_by_value(&parents);
+        let mut reached = HashSet::new();
+        let mut stack = roots;
+        while let Some(node) = stack.pop() {
+            reached.insert(node);
+            if let Some(indices) = child_indices.get(&node) {
+                stack.extend(indices.iter().map(|&i| children[i]));
+            }
+        }
+        if reached.len() != children.len() {
+            let cycle = children
+                .iter()
+                .filter(|c| !reached.contains(c))
+                .cloned()
+                .collect();
+            return Err(TreeError::Cycle(cycle));
+        }
+        Ok(Tree {
+            root,
+            parents,
+            children,
+        })
+    }
+
+    pub fn root(&self) -> usize {
+        /*!  - Returns the root label, `0` for a forest. */
+        self.root
+    }
+
+    pub fn parents(&self) -> &[usize] {
+        /*!  - Returns the parent label of each child (`0` for a root). */
+        &self.parents
+    }
+
+    pub fn children(&self) -> &[usize] {
+        /*!  - Returns the node labels. */
+        &self.children
+    }
+
+    pub fn len(&self) -> usize {
+        /*!  - Returns the number of nodes. */
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        /*!  - Always false since a tree has at least one node. */
+        self.children.is_empty()
+    }
+}
+
+/// A `Tree` over arbitrary node labels, numbered `1..=n` in the order the children are given.
+///
+/// The generators run on the numbered `tree()` and report those numbers (e.g. the `labels` of
+/// each `prep_args`) which `label` and `labels_of` map back to the nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelledTree<T> {
+    tree: Tree,
+    labels: Vec<T>,
+}
+
+impl<T: Clone + Ord + Hash> LabelledTree<T> {
+    pub fn new(
+        root: Option<T>,
+        parents: &[Option<T>],
+        children: &[T],
+    ) -> Result<Self, TreeError<T>> {
+        /*!  - Returns the numbered tree, where `None` parents mark roots and a `None` root is the forest. */
+        if parents.len() != children.len() {
+            return Err(TreeError::LengthMismatch {
+                parents: parents.len(),
+                children: children.len(),
+            });
+        }
+        let mut ids = HashMap::with_capacity(children.len());
+        for (index, c) in children.iter().enumerate() {
+            if ids.insert(c.clone(), index + 1).is_some() {
+                return Err(TreeError::DuplicateId(c.clone()));
+            }
+        }
+        let root_id = match &root {
+            Some(r) => *ids
+                .get(r)
+                .ok_or_else(|| TreeError::MissingRoot(r.clone()))?,
+            None => 0,
+        };
+        let parent_ids = parents
+            .iter()
+            .map(|p| match p {
+                Some(p) => ids
+                    .get(p)
+                    .cloned()
+                    .ok_or_else(|| TreeError::Orphan(p.clone())),
+                None => Ok(0),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let labels = children.to_vec();
+        let tree = Tree::new(root_id, parent_ids, (1..=labels.len()).collect())
+            .map_err(|e| e.map_labels(|id| labels[id - 1].clone()))?;
+        Ok(LabelledTree { tree, labels })
+    }
+
+    pub fn tree(&self) -> &Tree {
+        /*!  - Returns the numbered tree to hand to the generators. */
+        &self.tree
+    }
+
+    pub fn label(&self, id: usize) -> &T {
+        /*!  - Returns the label of a node number. */
+        &self.labels[id - 1]
+    }
+
+    pub fn labels_of(&self, ids: &[usize]) -> Vec<T> {
+        /*!  - Returns the labels of node numbers, such as the `labels` of a `prep_args`. */
+        ids.iter().map(|&id| self.label(id).clone()).collect()
+    }
+}
+
+pub fn group_indices_by_value(values: &[usize]) -> HashMap<usize, Vec<usize>> {
     /*!  - Returns HashMap keyed by unique values with occurance indices as the values. */
     let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
     for (index, &value) in values.iter().enumerate() {
@@ -9,16 +278,43 @@ pub(crate) fn group_indices_by_value(values: &[usize]) -> HashMap<usize, Vec<usi
     groups
 }
 
-pub(crate) fn arrange_by_traversal_pre_order(
+pub fn index_by_value(values: &[usize]) -> HashMap<usize, usize> {
+    /*!  - Returns HashMap keyed by unique values with their (last) index as the value. */
+    values.iter().enumerate().map(|(i, &v)| (v, i)).collect()
+}
+
+pub fn traversal_roots(
+    root: usize,
+    child_indices: &HashMap<usize, Vec<usize>>,
+    children: &[usize],
+) -> Vec<usize> {
+    /*!  - Returns the roots to traverse, every node whose parent is `0` when root is `0`.
+
+    A root of `0` selects the forest of all parentless nodes, in the order they're given.
+    */
+    if root != 0 {
+        return vec![root];
+    }
+    match child_indices.get(&0) {
+        Some(indices) => indices.iter().map(|&i| children[i]).collect(),
+        None => vec![],
+    }
+}
+
+pub fn arrange_by_traversal_pre_order(
     root: usize,
     parents: &[usize],
     children: &[usize],
 ) -> (Vec<usize>, Vec<usize>) {
-    /*!  - Returns children and parents in traversal pre-order. */
+    /*!  - Returns children and parents in traversal pre-order (of each tree for a `0` root). */
     let mut result_parent = vec![];
     let mut result_child = vec![];
-    let mut stack = vec![(root, None)];
     let child_indices = group_indices_by_value(parents);
+    let mut stack = traversal_roots(root, &child_indices, children)
+        .into_iter()
+        .rev()
+        .map(|r| (r, None))
+        .collect::<Vec<_>>();
 
     while let Some((node, parent)) = stack.pop() {
         result_child.push(node);
@@ -32,16 +328,19 @@ pub(crate) fn arrange_by_traversal_pre_order(
     (result_parent, result_child)
 }
 
-pub(crate) fn arrange_by_traversal_post_order(
+pub fn arrange_by_traversal_post_order(
     root: usize,
     parents: &[usize],
     children: &[usize],
 ) -> (Vec<usize>, Vec<usize>) {
-    /*!  - Returns children and parents in traversal post-order. */
+    /*!  - Returns children and parents in traversal post-order (of each tree for a `0` root). */
     let mut result_parent = vec![];
     let mut result_child = vec![];
-    let mut stack = vec![(root, None)];
     let child_indices = group_indices_by_value(parents);
+    let mut stack = traversal_roots(root, &child_indices, children)
+        .into_iter()
+        .map(|r| (r, None))
+        .collect::<Vec<_>>();
 
     while let Some((node, parent)) = stack.pop() {
         if let Some(child_indices) = child_indices.get(&node) {
@@ -57,10 +356,10 @@ pub(crate) fn arrange_by_traversal_post_order(
     (result_parent, result_child)
 }
 
-pub(crate) fn arrange_largest_subtrees(
+pub fn arrange_largest_subtrees(
     root: usize,
     parents: &[usize],
-    children: &Vec<usize>,
+    children: &[usize],
     left: bool,
 ) -> (Vec<usize>, Vec<usize>) {
     /*!  - Arranges subtrees of a rooted tree by size.*/
@@ -68,13 +367,18 @@ pub(crate) fn arrange_largest_subtrees(
     let mut result_children = vec![];
     let mut stack = vec![(root, 0)];
     let child_indices = group_indices_by_value(parents);
+    // Overflowing counts are all equally 'largest'.
+    let subtree_counts = count_subtrees_at_each(parents, children)
+        .into_iter()
+        .map(|c| c.unwrap_or(u128::MAX))
+        .collect::<Vec<_>>();
 
     let cmp = |a: &usize, b: &usize| {
-        let mut a_count = count_subtrees_at(children[*a], &child_indices, children);
-        let b_count = count_subtrees_at(children[*b], &child_indices, children);
+        let mut a_count = subtree_counts[*a];
+        let b_count = subtree_counts[*b];
         if a_count == b_count {
             // Keep stable order when equal (there is likely a better way to do this).
-            a_count += 1;
+            a_count = a_count.saturating_add(1);
             b_count.cmp(&a_count)
         } else if left {
             b_count.cmp(&a_count)
@@ -93,29 +397,240 @@ pub(crate) fn arrange_largest_subtrees(
         for child in node_children.iter() {
             stack.push((children[*child], node));
         }
-        result_parents.push(parent);
-        result_children.push(node);
+        // A forest's `0` root only holds the trees together.
+        if node != 0 {
+            result_parents.push(parent);
+            result_children.push(node);
+        }
     }
     (result_parents, result_children)
 }
 
-pub(crate) fn count_subtrees(root: usize, parents: &[usize], children: &Vec<usize>) -> usize {
-    /*!  - Return the total number of possible subtrees rooted at root. */
+pub fn count_subtrees(
+    root: usize,
+    parents: &[usize],
+    children: &[usize],
+) -> Result<u128, CountOverflowError> {
+    /*!  - Return the total number of possible subtrees rooted at root.
+
+    For a forest (a `0` root) this is the number of non-empty ideals of the forest.
+    */
+    let child_indices = group_indices_by_value(parents);
+    let count = count_subtrees_at(root, &child_indices, children)? - 1;
+    Ok(if root == 0 { count - 1 } else { count })
+}
+
+pub fn count_subtrees_at(
+    root: usize,
+    child_indices: &HashMap<usize, Vec<usize>>,
+    children: &[usize],
+) -> Result<u128, CountOverflowError> {
+    /*!  - Returns the number of subtrees rooted at the given node (plus one for the empty tree). */
+    let mut counts: HashMap<usize, u128> = HashMap::new();
+    for node in traversal_post_order_nodes(root, child_indices, children) {
+        let overflow = CountOverflowError { node };
+        let mut count: u128 = 1;
+        for child in child_indices.get(&node).into_iter().flatten() {
+            let child_count = counts.remove(&children[*child]).unwrap();
+            count = count.checked_mul(child_count).ok_or(overflow)?;
+        }
+        counts.insert(node, count.checked_add(1).ok_or(overflow)?);
+    }
+    Ok(counts[&root])
+}
+
+pub fn count_subtrees_at_each(
+    parents: &[usize],
+    children: &[usize],
+) -> Vec<Result<u128, CountOverflowError>> {
+    /*!  - Returns count_subtrees_at for every node, in the same order as the children.
+
+    Each count is computed once from its children's counts so this is O(n) for the whole tree.
+    */
+    let child_indices = group_indices_by_value(parents);
+    let label_indices = index_by_value(children);
+    let mut counts = vec![Ok(0); children.len()];
+    for node in traversal_post_order_nodes(0, &child_indices, children) {
+        let overflow = CountOverflowError { node };
+        let mut count = Ok(1u128);
+        for child in child_indices.get(&node).into_iter().flatten() {
+            count = count.and_then(|count| {
+                let child_count = counts[*child]?;
+                count.checked_mul(child_count).ok_or(overflow)
+            });
+        }
+        if let Some(&index) = label_indices.get(&node) {
+            counts[index] = count.and_then(|c| c.checked_add(1).ok_or(overflow));
+        }
+    }
+    counts
+}
+
+fn traversal_post_order_nodes(
+    root: usize,
+    child_indices: &HashMap<usize, Vec<usize>>,
+    children: &[usize],
+) -> Vec<usize> {
+    /*!  - Returns root and the nodes under it in traversal post-order. */
+    // A `0` root comes last, after the forest of every node without a parent.
+    let mut nodes = vec![];
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        nodes.push(node);
+        if let Some(indices) = child_indices.get(&node) {
+            stack.extend(indices.iter().map(|&i| children[i]));
+        }
+    }
+    nodes.reverse();
+    nodes
+}
+
+pub fn count_subtrees_by_size(
+    root: usize,
+    parents: &[usize],
+    children: &[usize],
+) -> Result<Vec<u128>, CountOverflowError> {
+    /*!  - Returns the number of subtrees rooted at root for each size `0..=n`.
+
+    The empty tree isn't counted, so the sum matches `count_subtrees`.
+    */
+    count_subtrees_by_size_upto(root, parents, children, children.len())
+}
+
+pub fn count_subtrees_by_size_upto(
+    root: usize,
+    parents: &[usize],
+    children: &[usize],
+    max_size: usize,
+) -> Result<Vec<u128>, CountOverflowError> {
+    /*!  - Returns the number of subtrees rooted at root for each size `0..=min(max_size, n)`.
+
+    Larger sizes are never combined so this is O(n * max_size) rather than O(n²).
+    */
     let child_indices = group_indices_by_value(parents);
-    count_subtrees_at(root, &child_indices, children) - 1
+    let max_size = std::cmp::min(max_size, children.len());
+    let mut counts = count_subtrees_by_size_at(root, &child_indices, children, max_size)?;
+    counts.resize(max_size + 1, 0);
+    Ok(counts)
 }
 
-pub(crate) fn count_subtrees_at(
+pub fn count_subtrees_by_size_at(
     root: usize,
     child_indices: &HashMap<usize, Vec<usize>>,
-    children: &Vec<usize>,
-) -> usize {
-    /*!  - Returns the number of subtrees rooted at the given node. */
-    let mut count = 1;
-    if let Some(c) = child_indices.get(&root) {
-        for child in c {
-            count *= count_subtrees_at(children[*child], child_indices, children);
+    children: &[usize],
+    max_size: usize,
+) -> Result<Vec<u128>, CountOverflowError> {
+    /*!  - Returns the number of subtrees rooted at the given node for each size up to max_size.
+
+    Each child either contributes nothing or one of its own rooted subtrees so the size
+    distributions are combined as the product of `(1 + child)` polynomials.
+    */
+    let mut node_counts: HashMap<usize, Vec<u128>> = HashMap::new();
+    for node in traversal_post_order_nodes(root, child_indices, children) {
+        let overflow = CountOverflowError { node };
+        // A forest's `0` root isn't a node so it adds nothing to the size.
+        let mut counts: Vec<u128> = if node == 0 { vec![1] } else { vec![0, 1] };
+        counts.truncate(max_size + 1);
+        let first = usize::from(node != 0);
+        for child in child_indices.get(&node).into_iter().flatten() {
+            let child_counts = node_counts.remove(&children[*child]).unwrap();
+            let mut combined = counts.clone();
+            let len = std::cmp::min(counts.len() + child_counts.len() - 1, max_size + 1);
+            combined.resize(len, 0);
+            for (i, &a) in counts.iter().enumerate().skip(first) {
+                for (j, &b) in child_counts.iter().enumerate().skip(1).take(len - i - 1) {
+                    let product = a.checked_mul(b).ok_or(overflow)?;
+                    combined[i + j] = combined[i + j].checked_add(product).ok_or(overflow)?;
+                }
+            }
+            counts = combined;
+        }
+        if node == 0 {
+            counts[0] = 0;
+        }
+        node_counts.insert(node, counts);
+    }
+    Ok(node_counts.remove(&root).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::sample_trees;
+
+    #[test]
+    fn invalid_trees_report_why() {
+        let cases = [
+            (
+                1,
+                vec![0],
+                vec![],
+                TreeError::LengthMismatch {
+                    parents: 1,
+                    children: 0,
+                },
+            ),
+            (1, vec![], vec![], TreeError::Empty),
+            (1, vec![0, 1], vec![1, 0], TreeError::ZeroLabel),
+            (1, vec![0, 1, 1], vec![1, 2, 2], TreeError::DuplicateId(2)),
+            (3, vec![0, 1], vec![1, 2], TreeError::MissingRoot(3)),
+            (2, vec![0, 1], vec![1, 2], TreeError::RootHasParent(2)),
+            (0, vec![2, 1], vec![1, 2], TreeError::NoRoot),
+            (
+                1,
+                vec![0, 0, 1],
+                vec![1, 2, 3],
+                TreeError::MultipleRoots(vec![1, 2]),
+            ),
+            (1, vec![0, 4], vec![1, 2], TreeError::Orphan(4)),
+            (
+                1,
+                vec![0, 3, 2, 1],
+                vec![1, 2, 3, 4],
+                TreeError::Cycle(vec![2, 3]),
+            ),
+            (
+                0,
+                vec![0, 3, 2],
+                vec![1, 2, 3],
+                TreeError::Cycle(vec![2, 3]),
+            ),
+        ];
+        for (root, parents, children, error) in cases {
+            assert_eq!(Tree::new(root, parents, children), Err(error));
+        }
+    }
+
+    #[test]
+    fn forests_and_single_nodes_are_trees() {
+        assert!(Tree::new(1, vec![0], vec![1]).is_ok());
+        assert!(Tree::new(0, vec![0], vec![1]).is_ok());
+        assert!(Tree::new(0, vec![0, 0, 1], vec![1, 2, 3]).is_ok());
+    }
+
+    #[test]
+    fn labelled_errors_report_labels() {
+        let tree = LabelledTree::new(Some("a"), &[None, Some("a"), Some("c")], &["a", "b", "b"]);
+        assert_eq!(tree, Err(TreeError::DuplicateId("b")));
+        let tree = LabelledTree::new(Some("a"), &[None, Some("c"), Some("b")], &["a", "b", "c"]);
+        assert_eq!(tree, Err(TreeError::Cycle(vec!["b", "c"])));
+        let tree = LabelledTree::new(None, &[None, Some("a")], &["a", "b"]).unwrap();
+        assert_eq!(tree.labels_of(&[2, 1]), ["b", "a"]);
+    }
+
+    #[test]
+    fn subtrees_by_size_match_brute_force() {
+        for tree in sample_trees() {
+            let mut expected = vec![0u128; tree.len() + 1];
+            for ideal in brute_force_ideals(&tree) {
+                expected[ideal.len()] += 1;
+            }
+            let by_size =
+                count_subtrees_by_size(tree.root(), tree.parents(), tree.children()).unwrap();
+            assert_eq!(by_size, expected, "{tree:?}");
+            let count = count_subtrees(tree.root(), tree.parents(), tree.children()).unwrap();
+            assert_eq!(by_size.iter().sum::<u128>(), count);
         }
     }
-    count + 1
 }
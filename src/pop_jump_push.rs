@@ -1,52 +1,444 @@
 //! # Implementation of the Pop Jump Push Algorithm for generating all ideals of an arborescence.
 //!
 
+use std::io::Write;
+use std::ops::ControlFlow;
+use std::ops::Range;
 use std::time::Instant;
 
+use crate::index::compact_indices;
+use crate::index::ideal_mask;
+use crate::index::IdealMask;
+use crate::index::IndexWidth;
+use crate::index::NodeIndex;
 use crate::node_manipulation::arrange_by_traversal_pre_order;
 use crate::node_manipulation::count_subtrees;
+use crate::node_manipulation::count_subtrees_by_size_upto;
+use crate::node_manipulation::index_by_value;
+use crate::node_manipulation::CountOverflowError;
+use crate::node_manipulation::Tree;
+use crate::rank::shard_range;
+use crate::rank::IdealRanker;
+use crate::timing::time_runs;
+use crate::visitor::IdealVisitor;
+use crate::visitor::IncrementalIdealVisitor;
 
-fn pop_jump_push(
+pub fn pop_jump_push<I: NodeIndex, V: IdealVisitor<[I]>>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<I>,
+    jump_indices: &[I],
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm.
+
+    The indices can be any `NodeIndex` fitting `num_nodes`, e.g. `compact_indices` of `prep_args`;
+    only it and the parallel generators aren't limited to `usize` indices.
+    Returns `ControlFlow::Break` if the visitor stopped the enumeration, in which case
+    `sequence_indices` still holds the last visited ideal.
+    */
+    while !sequence_indices.is_empty() {
+        visitor.visit(sequence_indices)?;
+        let index = jump_indices[sequence_indices.pop().unwrap().to_usize()].to_usize();
+        if index < num_nodes {
+            sequence_indices.extend((index..num_nodes).map(I::from_usize));
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_incremental<V: IncrementalIdealVisitor>(
     num_nodes: usize,
     sequence_indices: &mut Vec<usize>,
     jump_indices: &[usize],
-    labels: &[usize],
-    output: u8,
-) {
-    /*!  - Implements the Pop Jump Push algorithm. */
-    while !sequence_indices.is_empty() {
-        visit(sequence_indices, labels, output);
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm reporting each pop and push before the visits.
+
+    The starting `sequence_indices` are first pushed as runs of consecutive indices so the
+    visitor's state begins from the empty ideal. Every pushed index is later popped, so the
+    events cost O(1) amortized per visit. The last pop, which empties the stack, is also reported.
+    */
+    let mut start = 0;
+    for end in 1..=sequence_indices.len() {
+        if end == sequence_indices.len() || sequence_indices[end] != sequence_indices[end - 1] + 1 {
+            visitor.push_range(sequence_indices[start]..sequence_indices[end - 1] + 1);
+            start = end;
+        }
+    }
+    while let Some(&last) = sequence_indices.last() {
+        visitor.visit(sequence_indices)?;
+        sequence_indices.pop();
+        visitor.pop(last);
+        let index = jump_indices[last];
+        if index < num_nodes {
+            sequence_indices.extend(index..num_nodes);
+            visitor.push_range(index..num_nodes);
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_mask<M: IdealMask, V: IdealVisitor<M>>(
+    ideal: &mut M,
+    toggle_masks: &[M],
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm on a bit mask of pre-order indices.
+
+    The stack is always ascending so its last index is the highest set bit, and popping it and
+    pushing its jump suffix toggles exactly the bits of `generate_toggle_masks`. Returns
+    `ControlFlow::Break` if the visitor stopped the enumeration, in which case `ideal` is still the
+    last visited ideal.
+    */
+    while *ideal != M::EMPTY {
+        visitor.visit(ideal)?;
+        *ideal = *ideal ^ toggle_masks[ideal.last_index()];
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_complement<V: IdealVisitor<[Range<usize>]>>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<usize>,
+    jump_indices: &[usize],
+    complement: &mut Vec<Range<usize>>,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm visiting the complement of each ideal.
+
+    The complement is kept as ranges of the pre-order indices missing from the ideal. Popping
+    `x` only drops the trailing `[x + 1..n)` range and adds the excluded subtree `[x..j[x])`, so
+    each step is O(1) on top of the pop and push. Once the enumeration completes the
+    complement is `[0..n)`.
+    */
+    complement.clear();
+    let mut next = 0;
+    for &index in sequence_indices.iter() {
+        if index > next {
+            complement.push(next..index);
+        }
+        next = index + 1;
+    }
+    if next < num_nodes {
+        complement.push(next..num_nodes);
+    }
+    while let Some(&last) = sequence_indices.last() {
+        visitor.visit(complement)?;
+        sequence_indices.pop();
+        let index = jump_indices[last];
+        // Everything after the popped index was excluded; now only its subtree is.
+        if complement.last().is_some_and(|r| r.start > last) {
+            complement.pop();
+        }
+        match complement.last_mut() {
+            Some(range) if range.end == last => range.end = index,
+            _ => complement.push(last..index),
+        }
+        if index < num_nodes {
+            sequence_indices.extend(index..num_nodes);
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_filters<V: IdealVisitor<[Range<usize>]>>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<usize>,
+    jump_indices: &[usize],
+    complement: &mut Vec<Range<usize>>,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm visiting every order filter (up-set).
+
+    The filters are the complements of the ideals followed by the set of every node, the
+    complement of the empty ideal which `pop_jump_push` doesn't visit.
+    */
+    pop_jump_push_complement(
+        num_nodes,
+        sequence_indices,
+        jump_indices,
+        complement,
+        visitor,
+    )?;
+    visitor.visit(complement)
+}
+
+pub fn pop_jump_push_counted<V: IdealVisitor>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<usize>,
+    jump_indices: &[usize],
+    count: u128,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm stopping after count visits.
+
+    Starting from an unranked `sequence_indices` this visits a contiguous range of ranks.
+    */
+    let mut remaining = count;
+    while remaining > 0 && !sequence_indices.is_empty() {
+        visitor.visit(sequence_indices)?;
+        remaining -= 1;
         let index = jump_indices[sequence_indices.pop().unwrap()];
         if index < num_nodes {
             sequence_indices.extend(index..num_nodes);
         }
     }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_sized<V: IdealVisitor>(
+    num_nodes: usize,
+    jump_indices: &[usize],
+    min_size: usize,
+    max_size: usize,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm visiting only ideals with a size in
+    `[min_size, max_size]`.
+
+    Pushes are truncated to `[i..i + max_size - len)` and popping continues, without visiting,
+    while the remaining `[i..n)` can't bring the ideal up to `min_size`.
+    */
+    if min_size > max_size {
+        return ControlFlow::Continue(());
+    }
+    let mut sequence_indices = Vec::with_capacity(std::cmp::min(num_nodes, max_size));
+    let mut index = 0;
+    loop {
+        while sequence_indices.len() + num_nodes - index < min_size {
+            match sequence_indices.pop() {
+                Some(last) => index = jump_indices[last],
+                None => return ControlFlow::Continue(()),
+            }
+        }
+        let end = std::cmp::min(
+            num_nodes,
+            index + max_size.saturating_sub(sequence_indices.len()),
+        );
+        sequence_indices.extend(index..end);
+        if sequence_indices.is_empty() {
+            return ControlFlow::Continue(());
+        }
+        visitor.visit(&sequence_indices)?;
+        index = jump_indices[sequence_indices.pop().unwrap()];
+    }
+}
+
+/// A lending iterator over all ideals of an arborescence in Pop Jump Push order.
+///
+/// Each ideal is yielded as a slice of pre-order indices borrowed from the iterator's internal
+/// stack; use `labels` to map the indices back to the original node labels.
+pub struct IdealIterator {
+    num_nodes: usize,
+    sequence_indices: Vec<usize>,
+    jump_indices: Vec<usize>,
+    labels: Vec<usize>,
+    started: bool,
+}
+
+impl IdealIterator {
+    pub fn new(tree: &Tree) -> Self {
+        /*!  - Prepares the tree and positions the iterator before the first (full) ideal. */
+        let (num_nodes, sequence_indices, jump_indices, labels) = prep_args(tree, 0);
+        IdealIterator {
+            num_nodes,
+            sequence_indices,
+            jump_indices,
+            labels,
+            started: false,
+        }
+    }
+
+    pub fn next_ideal(&mut self) -> Option<&[usize]> {
+        /*!  - Advances by one pop, jump and push and returns the resulting ideal. */
+        if self.started {
+            let index = self.jump_indices[self.sequence_indices.pop()?];
+            if index < self.num_nodes {
+                self.sequence_indices.extend(index..self.num_nodes);
+            }
+        }
+        self.started = true;
+        if self.sequence_indices.is_empty() {
+            None
+        } else {
+            Some(&self.sequence_indices)
+        }
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        /*!  - Returns the node labels indexed by pre-order index. */
+        &self.labels
+    }
+
+    pub fn jump_indices(&self) -> &[usize] {
+        /*!  - Returns the pre-order jump indices driving the iteration. */
+        &self.jump_indices
+    }
+}
+
+/// Prints ideals according to the cli `output` setting.
+#[doc(hidden)]
+pub struct OutputVisitor<'a> {
+    pub labels: &'a [usize],
+    pub output: u8,
+}
+
+impl<I: NodeIndex> IdealVisitor<[I]> for OutputVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[I]) -> ControlFlow<()> {
+        visit(ideal, self.labels, self.output);
+        ControlFlow::Continue(())
+    }
+}
+
+/// Prints each ideal as its mask of pre-order indices, the cli `output == 6`.
+#[doc(hidden)]
+pub struct MaskVisitor;
+
+impl<M: IdealMask> IdealVisitor<M> for MaskVisitor {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &M) -> ControlFlow<()> {
+        println!("{ideal:#x}");
+        ControlFlow::Continue(())
+    }
+}
+
+/// Prints only the nodes removed (`-label`) and added (`+label`) since the previous ideal.
+///
+/// Every step of `pop_jump_push` pops one index and pushes a suffix so the kept prefix is all but
+/// the last index of the previous ideal and only its length and last index need remembering.
+#[derive(Default)]
+#[doc(hidden)]
+pub struct DeltaVisitor<'a> {
+    pub labels: &'a [usize],
+    pub previous_len: usize,
+    pub previous_last: Option<usize>,
+}
+
+impl DeltaVisitor<'_> {
+    pub fn delta<I: NodeIndex>(&mut self, ideal: &[I]) -> String {
+        /*!  - Returns the changes since the previous ideal and remembers this one. */
+        let mut line = String::new();
+        let kept_len = match self.previous_last {
+            Some(index) => {
+                line.push_str(&format!("-{}", self.labels[index]));
+                self.previous_len - 1
+            }
+            None => 0,
+        };
+        for &index in ideal[kept_len..].iter() {
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&format!("+{}", self.labels[index.to_usize()]));
+        }
+        self.previous_len = ideal.len();
+        self.previous_last = ideal.last().map(|index| index.to_usize());
+        line
+    }
 }
 
-pub(crate) fn visit(ideal: &[usize], labels: &[usize], output: u8) {
+impl<I: NodeIndex> IdealVisitor<[I]> for DeltaVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[I]) -> ControlFlow<()> {
+        println!("{}", self.delta(ideal));
+        ControlFlow::Continue(())
+    }
+}
+
+/// Writes ideals in the same format as `OutputVisitor` prints them and counts them.
+///
+/// A failed write stops the enumeration and is kept in `error`.
+pub struct WriterVisitor<'a, W: Write> {
+    pub labels: &'a [usize],
+    pub output: u8,
+    pub writer: W,
+    pub count: usize,
+    pub error: Option<std::io::Error>,
+}
+
+impl<I: NodeIndex, W: Write> IdealVisitor<[I]> for WriterVisitor<'_, W> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[I]) -> ControlFlow<()> {
+        let ideal = std::hint::black_box(ideal);
+        let result = if self.output >= 2 {
+            writeln!(
+                self.writer,
+                "{}",
+                format_ideal(ideal, self.labels, self.output)
+            )
+        } else {
+            Ok(())
+        };
+        self.count += 1;
+        match result {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => {
+                self.error = Some(e);
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+/// Prints complements or filters according to the cli `output` setting.
+#[doc(hidden)]
+pub struct ComplementVisitor<'a> {
+    pub labels: &'a [usize],
+    pub output: u8,
+}
+
+impl IdealVisitor<[Range<usize>]> for ComplementVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ranges: &[Range<usize>]) -> ControlFlow<()> {
+        let ranges = std::hint::black_box(ranges);
+        if self.output == 2 {
+            println!("{ranges:?}")
+        } else if self.output >= 3 {
+            let mut result = ranges
+                .iter()
+                .flat_map(|r| self.labels[r.clone()].iter().cloned())
+                .collect::<Vec<_>>();
+            result.sort();
+            println!("{result:?}");
+        };
+        ControlFlow::Continue(())
+    }
+}
+
+#[inline(always)]
+#[doc(hidden)]
+pub fn visit<I: NodeIndex>(ideal: &[I], labels: &[usize], output: u8) {
     /*!  -  Process/output ideals. */
     // This just ensures the compiler doesn't optimize anything away during `output == 0` benchmarking.
     let ideal = std::hint::black_box(ideal);
 
+    if output >= 2 {
+        println!("{}", format_ideal(ideal, labels, output));
+    };
+}
+
+#[doc(hidden)]
+pub fn format_ideal<I: NodeIndex>(ideal: &[I], labels: &[usize], output: u8) -> String {
+    /*!  - Returns the pre-order indices for output 2, otherwise the sorted labels. */
     if output == 2 {
-        println!("{ideal:?}")
-    } else if output >= 3 {
-        let mut result = ideal.iter().map(|i| labels[*i]).collect::<Vec<_>>();
+        format!("{ideal:?}")
+    } else {
+        let mut result = ideal
+            .iter()
+            .map(|i| labels[i.to_usize()])
+            .collect::<Vec<_>>();
         result.sort();
-        println!("{result:?}");
-    };
+        format!("{result:?}")
+    }
 }
 
-pub(crate) fn prep_args(
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
-    output: u8,
-) -> (usize, Vec<usize>, Vec<usize>, Vec<usize>) {
+pub fn prep_args(tree: &Tree, output: u8) -> (usize, Vec<usize>, Vec<usize>, Vec<usize>) {
     /*!  -  Return a tuple of the arguments for calling pop_jump_push. */
-    let num_nodes = children.len();
+    let num_nodes = tree.len();
     let sequence_indices = (0..num_nodes).collect::<Vec<_>>();
-    let (parents, children) = arrange_by_traversal_pre_order(root, parents, children);
+    let (parents, children) =
+        arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
     let jump_indices = generate_jump_indices(&parents, &children);
     if output == 1 {
         let arg = "num_nodes";
@@ -59,7 +451,16 @@ pub(crate) fn prep_args(
     (num_nodes, sequence_indices, jump_indices, children)
 }
 
-pub(crate) fn generate_jump_indices(parents: &[usize], children: &[usize]) -> Vec<usize> {
+pub fn generate_toggle_masks<M: IdealMask>(num_nodes: usize, jump_indices: &[usize]) -> Vec<M> {
+    /*!  - Returns each node's bit together with the bits of the indices its jump pushes. */
+    jump_indices
+        .iter()
+        .enumerate()
+        .map(|(index, &jump)| M::range(index..index + 1) ^ M::range(jump..num_nodes))
+        .collect()
+}
+
+pub fn generate_jump_indices(parents: &[usize], children: &[usize]) -> Vec<usize> {
     /*!  - Returns the pre-order traversal end indices for the subtree rooted at each node.
 
     This is a one past last value; range(i, i_end) aka [i..i_end) covers all nodes in the subtree.
@@ -69,9 +470,12 @@ pub(crate) fn generate_jump_indices(parents: &[usize], children: &[usize]) -> Ve
     let num_nodes = children.len();
     let mut end_indices = vec![0; num_nodes];
     let mut parent_indices = vec![0];
+    let label_indices = index_by_value(children);
 
-    for p in parents[1..].iter() {
-        parent_indices.push(children.iter().position(|&x| x == *p).unwrap());
+    for (index, p) in parents.iter().enumerate().skip(1) {
+        // The roots of a forest are their own parents here.
+        let parent_index = label_indices.get(p).cloned();
+        parent_indices.push(parent_index.unwrap_or(index));
     }
     for index in (0..num_nodes).rev() {
         end_indices[index] = std::cmp::max(index + 1, end_indices[index]);
@@ -81,37 +485,83 @@ pub(crate) fn generate_jump_indices(parents: &[usize], children: &[usize]) -> Ve
     end_indices
 }
 
-pub(crate) fn pop_jump_push_main(
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
-    output: u8,
-    reps: u32,
-) {
+#[doc(hidden)]
+pub fn pop_jump_push_main(tree: &Tree, output: u8, reps: u32) -> Result<(), CountOverflowError> {
     /*! Rust doesn't have stable generators so the whole tree gets processed with 'visits'. */
-    let ideals_count = count_subtrees(children[0], parents, children);
-    let num_nodes = children.len();
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
+    let num_nodes = tree.len();
     let ttl_ideals = ideals_count as f64 * reps as f64;
     println!(
         "Generating {ideals_count} ideals from {num_nodes} nodes {reps} times ({ttl_ideals}).\n"
     );
 
-    let args = prep_args(root, parents, children, output);
+    let args = prep_args(tree, output);
+
+    // Masks are timed for output 6, otherwise the smallest index type fitting `num_nodes`.
+    let width = IndexWidth::fitting(num_nodes);
+    let (end_time_delta, time_delta) = if output == 6 && num_nodes <= u64::BITS as usize {
+        time_pop_jump_push_mask::<u64>(&args, reps)
+    } else if output == 6 {
+        time_pop_jump_push_mask::<u128>(&args, reps)
+    } else {
+        match width {
+            IndexWidth::U8 => time_pop_jump_push::<u8>(&args, output, reps),
+            IndexWidth::U16 => time_pop_jump_push::<u16>(&args, output, reps),
+            IndexWidth::U32 => time_pop_jump_push::<u32>(&args, output, reps),
+            IndexWidth::Usize => time_pop_jump_push::<usize>(&args, output, reps),
+        }
+    };
+
+    println!("\tCompleted generating ideals...");
+    println!("\tAvg Duration per tree {}", end_time_delta / reps as f64);
+    println!("\tBest Duration per tree {time_delta}");
+    println!(
+        "\t{} ns avg per ideal",
+        (end_time_delta / reps as f64) / ideals_count as f64 * 1e9
+    );
+    println!(
+        "\t{} ns best per ideal\n",
+        time_delta / ideals_count as f64 * 1e9
+    );
+
+    if output == 0 && width != IndexWidth::Usize {
+        let (usize_end_time_delta, usize_time_delta) =
+            time_pop_jump_push::<usize>(&args, output, reps);
+        println!("\tCompared with usize indices...");
+        println!(
+            "\t{} ns avg per ideal",
+            (usize_end_time_delta / reps as f64) / ideals_count as f64 * 1e9
+        );
+        println!(
+            "\t{} ns best per ideal",
+            usize_time_delta / ideals_count as f64 * 1e9
+        );
+        println!(
+            "\t{:.2}x avg and {:.2}x best speedup with {width} indices\n",
+            usize_end_time_delta / end_time_delta,
+            usize_time_delta / time_delta
+        );
+    }
+    Ok(())
+}
+
+fn time_pop_jump_push_mask<M: IdealMask>(
+    args: &(usize, Vec<usize>, Vec<usize>, Vec<usize>),
+    reps: u32,
+) -> (f64, f64) {
+    /*!  - Returns the total and best durations of reps runs printing `M` masks. */
+    let (num_nodes, sequence_indices, jump_indices, _) = args;
+    let sequence_mask = ideal_mask::<M>(sequence_indices);
+    let toggle_masks = generate_toggle_masks::<M>(*num_nodes, jump_indices);
 
     let start_time = Instant::now();
     let mut time_delta = f64::MAX;
     let mut i = 0;
     while i < reps {
         i += 1;
-        let (num_nodes, mut subtree_indices, jump_indices, labels) = args.clone();
+        let mut ideal = sequence_mask;
         let run_start_time = Instant::now();
-        pop_jump_push(
-            num_nodes,
-            &mut subtree_indices,
-            &jump_indices,
-            &labels,
-            output,
-        );
+        let _ = pop_jump_push_mask(&mut ideal, &toggle_masks, &mut MaskVisitor);
         let run_time_delta = run_start_time.elapsed().as_secs_f64();
         time_delta = if time_delta < run_time_delta {
             time_delta
@@ -119,17 +569,467 @@ pub(crate) fn pop_jump_push_main(
             run_time_delta
         }
     }
-    let end_time_delta = start_time.elapsed().as_secs_f64();
+    (start_time.elapsed().as_secs_f64(), time_delta)
+}
 
-    println!("\tCompleted generating ideals...");
-    println!("\tAvg Duration per tree {}", end_time_delta / reps as f64);
-    println!("\tBest Duration per tree {time_delta}");
+fn time_pop_jump_push<I: NodeIndex>(
+    args: &(usize, Vec<usize>, Vec<usize>, Vec<usize>),
+    output: u8,
+    reps: u32,
+) -> (f64, f64) {
+    /*!  - Returns the total and best durations of reps runs with `I` indices. */
+    let (num_nodes, sequence_indices, jump_indices, labels) = args;
+    let sequence_indices = compact_indices::<I>(sequence_indices);
+    let jump_indices = compact_indices::<I>(jump_indices);
+
+    let start_time = Instant::now();
+    let mut time_delta = f64::MAX;
+    let mut i = 0;
+    while i < reps {
+        i += 1;
+        let mut subtree_indices = sequence_indices.clone();
+        let run_start_time = Instant::now();
+        if output == 5 {
+            let mut visitor = DeltaVisitor {
+                labels,
+                ..Default::default()
+            };
+            let _ = pop_jump_push(
+                *num_nodes,
+                &mut subtree_indices,
+                &jump_indices,
+                &mut visitor,
+            );
+        } else {
+            let mut visitor = OutputVisitor { labels, output };
+            let _ = pop_jump_push(
+                *num_nodes,
+                &mut subtree_indices,
+                &jump_indices,
+                &mut visitor,
+            );
+        }
+        let run_time_delta = run_start_time.elapsed().as_secs_f64();
+        time_delta = if time_delta < run_time_delta {
+            time_delta
+        } else {
+            run_time_delta
+        }
+    }
+    (start_time.elapsed().as_secs_f64(), time_delta)
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_complement_main(
+    tree: &Tree,
+    filters: bool,
+    output: u8,
+    reps: u32,
+) -> Result<(), CountOverflowError> {
+    /*! Times reps runs of `pop_jump_push_filters` or `pop_jump_push_complement` per filters.
+
+    The filters include the whole tree, the complement of the empty ideal, so there is one more
+    filter than there are complements.
+    */
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
+    let (items_count, items, item) = match filters {
+        true => (ideals_count + 1, "filters", "filter"),
+        false => (ideals_count, "complements", "complement"),
+    };
+    let num_nodes = tree.len();
+    let ttl_items = items_count as f64 * reps as f64;
     println!(
-        "\t{} ns avg per ideal",
-        (end_time_delta / reps as f64) / ideals_count as f64 * 1e9
+        "Generating {items_count} {items} from {num_nodes} nodes {reps} times ({ttl_items}).\n"
     );
-    println!(
-        "\t{} ns best per ideal\n",
-        time_delta / ideals_count as f64 * 1e9
+
+    let (num_nodes, sequence_indices, jump_indices, labels) = prep_args(tree, output);
+
+    let timing = time_runs(
+        reps,
+        || {
+            let visitor = ComplementVisitor {
+                labels: &labels,
+                output,
+            };
+            (
+                sequence_indices.clone(),
+                Vec::with_capacity(num_nodes),
+                visitor,
+            )
+        },
+        |(mut subtree_indices, mut complement, mut visitor)| {
+            let _ = match filters {
+                true => pop_jump_push_filters(
+                    num_nodes,
+                    &mut subtree_indices,
+                    &jump_indices,
+                    &mut complement,
+                    &mut visitor,
+                ),
+                false => pop_jump_push_complement(
+                    num_nodes,
+                    &mut subtree_indices,
+                    &jump_indices,
+                    &mut complement,
+                    &mut visitor,
+                ),
+            };
+        },
+    );
+    timing.report(items_count, items, item);
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_sized_main(
+    tree: &Tree,
+    min_size: usize,
+    max_size: usize,
+    output: u8,
+    reps: u32,
+) -> Result<(), CountOverflowError> {
+    /*! Times reps runs of `pop_jump_push_sized`, printing ideals of min_size..=max_size nodes.
+
+    Only the ideals in the size range are counted, from the per-size counts of the subtrees.
+    */
+    let size_counts =
+        count_subtrees_by_size_upto(tree.root(), tree.parents(), tree.children(), max_size)?;
+    let ideals_count: u128 = size_counts
+        .iter()
+        .take(max_size.saturating_add(1))
+        .skip(min_size)
+        .sum();
+    let num_nodes = tree.len();
+    let ttl_ideals = ideals_count as f64 * reps as f64;
+    print!("Generating {ideals_count} ideals of {min_size}..={max_size} nodes ");
+    println!("from {num_nodes} nodes {reps} times ({ttl_ideals}).\n");
+
+    let (num_nodes, _, jump_indices, labels) = prep_args(tree, output);
+    if output == 1 {
+        let arg = "size_counts";
+        println!("{arg:>18}: {size_counts:?}");
+    }
+
+    let timing = time_runs(
+        reps,
+        || OutputVisitor {
+            labels: &labels,
+            output,
+        },
+        |mut visitor| {
+            let _ = pop_jump_push_sized(num_nodes, &jump_indices, min_size, max_size, &mut visitor);
+        },
+    );
+    timing.report(ideals_count, "ideals", "ideal");
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_shard_main(
+    tree: &Tree,
+    shard: u128,
+    num_shards: u128,
+    output: u8,
+    reps: u32,
+) -> Result<(), CountOverflowError> {
+    /*! Processes the ranks of one shard so shards 0..num_shards together visit every ideal. */
+    let ranker = IdealRanker::new(tree)?;
+    let (start, end) = shard_range(ranker.count(), shard, num_shards);
+    let ideals_count = end - start;
+    let num_nodes = tree.len();
+    let ttl_ideals = ideals_count as f64 * reps as f64;
+    print!(
+        "Generating {ideals_count} ideals (ranks {start}..{end}) of shard {shard}/{num_shards} "
+    );
+    println!("from {num_nodes} nodes {reps} times ({ttl_ideals}).\n");
+
+    let labels = ranker.labels();
+    if output == 1 {
+        let _ = prep_args(tree, output);
+        let arg = "suffix_counts";
+        println!("{arg:>18}: {:?}", ranker.suffix_counts());
+        let arg = "ranks";
+        println!("{arg:>18}: {start}..{end}");
+    }
+
+    let timing = time_runs(
+        reps,
+        || OutputVisitor { labels, output },
+        |mut visitor| {
+            let _ = ranker.visit_range(start, end, &mut visitor);
+        },
     );
+    timing.report(ideals_count, "ideals", "ideal");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::replay_delta;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sequential_ideals;
+    use crate::test_support::sorted_labels;
+
+    /// Keeps its own stack from the pops and pushes, checking it against each visited ideal.
+    #[derive(Default)]
+    struct StackVisitor {
+        stack: Vec<usize>,
+        ideals: Vec<Vec<usize>>,
+    }
+
+    impl IncrementalIdealVisitor for StackVisitor {
+        fn pop(&mut self, index: usize) {
+            assert_eq!(self.stack.pop(), Some(index));
+        }
+
+        fn push_range(&mut self, range: Range<usize>) {
+            assert!(!range.is_empty());
+            self.stack.extend(range);
+        }
+
+        fn visit(&mut self, ideal: &[usize]) -> ControlFlow<()> {
+            assert_eq!(self.stack, ideal);
+            self.ideals.push(ideal.to_vec());
+            ControlFlow::Continue(())
+        }
+    }
+
+    fn visit_ranges(tree: &Tree, filters: bool) -> (Vec<Vec<usize>>, Vec<usize>) {
+        let (num_nodes, mut sequence_indices, jump_indices, labels) = prep_args(tree, 0);
+        let generate = match filters {
+            true => pop_jump_push_filters,
+            false => pop_jump_push_complement,
+        };
+        let mut sets = vec![];
+        let _ = generate(
+            num_nodes,
+            &mut sequence_indices,
+            &jump_indices,
+            &mut vec![],
+            &mut |ranges: &[Range<usize>]| {
+                assert!(ranges.iter().all(|range| !range.is_empty()));
+                assert!(ranges.windows(2).all(|pair| pair[0].end < pair[1].start));
+                sets.push(ranges.iter().cloned().flatten().collect());
+                ControlFlow::Continue(())
+            },
+        );
+        (sets, labels)
+    }
+
+    #[test]
+    fn complements_are_the_missing_indices_of_each_ideal() {
+        for tree in sample_trees() {
+            let (complements, _) = visit_ranges(&tree, false);
+            let expected = sequential_ideals(&tree)
+                .iter()
+                .map(|ideal| (0..tree.len()).filter(|i| !ideal.contains(i)).collect())
+                .collect::<Vec<Vec<usize>>>();
+            assert_eq!(complements, expected, "{tree:?}");
+        }
+    }
+
+    #[test]
+    fn filters_are_every_up_set() {
+        for tree in sample_trees() {
+            let (filters, labels) = visit_ranges(&tree, true);
+            assert_eq!(filters.last(), Some(&(0..tree.len()).collect()));
+            let num_filters = filters.len();
+            let filters = filters
+                .iter()
+                .map(|filter| sorted_labels(filter, &labels))
+                .collect::<BTreeSet<_>>();
+            let mut expected = brute_force_ideals(&tree)
+                .iter()
+                .map(|ideal| {
+                    let mut filter = tree.children().to_vec();
+                    filter.retain(|node| !ideal.contains(node));
+                    filter.sort();
+                    filter
+                })
+                .collect::<BTreeSet<_>>();
+            let mut every_node = tree.children().to_vec();
+            every_node.sort();
+            expected.insert(every_node);
+            assert_eq!(num_filters, expected.len());
+            assert_eq!(filters, expected, "{tree:?}");
+        }
+    }
+
+    #[test]
+    fn sized_ideals_are_the_ideals_within_bounds_in_order() {
+        for tree in sample_trees() {
+            let (num_nodes, _, jump_indices, _) = prep_args(&tree, 0);
+            let all_ideals = sequential_ideals(&tree);
+            for min_size in 0..=num_nodes + 1 {
+                for max_size in min_size.saturating_sub(1)..=num_nodes + 1 {
+                    let mut ideals = vec![];
+                    let _ = pop_jump_push_sized(
+                        num_nodes,
+                        &jump_indices,
+                        min_size,
+                        max_size,
+                        &mut |ideal: &[usize]| {
+                            ideals.push(ideal.to_vec());
+                            ControlFlow::Continue(())
+                        },
+                    );
+                    let expected = all_ideals
+                        .iter()
+                        .filter(|ideal| (min_size..=max_size).contains(&ideal.len()))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    assert_eq!(ideals, expected, "[{min_size}, {max_size}] of {tree:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deltas_replay_to_each_ideal() {
+        for tree in sample_trees() {
+            let (num_nodes, mut sequence_indices, jump_indices, labels) = prep_args(&tree, 0);
+            let mut delta_visitor = DeltaVisitor {
+                labels: &labels,
+                ..Default::default()
+            };
+            let mut replayed = BTreeSet::new();
+            let mut count = 0;
+            let _ = pop_jump_push(
+                num_nodes,
+                &mut sequence_indices,
+                &jump_indices,
+                &mut |ideal: &[usize]| {
+                    replay_delta(&mut replayed, &delta_visitor.delta(ideal));
+                    let expected = sorted_labels(ideal, &labels);
+                    assert_eq!(replayed.iter().cloned().collect::<Vec<_>>(), expected);
+                    count += 1;
+                    ControlFlow::Continue(())
+                },
+            );
+            assert_eq!(count, brute_force_ideals(&tree).len(), "{tree:?}");
+        }
+    }
+
+    #[test]
+    fn incremental_events_track_the_stack() {
+        for tree in sample_trees() {
+            let (num_nodes, mut sequence_indices, jump_indices, _) = prep_args(&tree, 0);
+            let mut visitor = StackVisitor::default();
+            let _ = pop_jump_push_incremental(
+                num_nodes,
+                &mut sequence_indices,
+                &jump_indices,
+                &mut visitor,
+            );
+            let ideals = sequential_ideals(&tree);
+            assert_eq!(visitor.ideals, ideals, "{tree:?}");
+            assert!(visitor.stack.is_empty());
+
+            // Resuming mid-enumeration first pushes the unranked stack as runs.
+            let ranker = IdealRanker::new(&tree).unwrap();
+            let ranks = [1, ideals.len() / 2, ideals.len() - 1];
+            for rank in ranks.into_iter().filter(|&rank| rank < ideals.len()) {
+                let mut sequence_indices = ranker.unrank(rank as u128).unwrap();
+                let mut visitor = StackVisitor::default();
+                let _ = pop_jump_push_incremental(
+                    num_nodes,
+                    &mut sequence_indices,
+                    &jump_indices,
+                    &mut visitor,
+                );
+                assert_eq!(visitor.ideals, ideals[rank..], "rank {rank} of {tree:?}");
+                assert!(visitor.stack.is_empty());
+            }
+        }
+    }
+
+    fn two_chains(num_nodes: usize) -> Tree {
+        // The root's children start chains of about half the remaining nodes each.
+        let split = num_nodes / 2 + 1;
+        let parents = (1..=num_nodes)
+            .map(|node| match node {
+                1 => 0,
+                2 => 1,
+                _ if node == split + 1 => 1,
+                _ => node - 1,
+            })
+            .collect();
+        Tree::new(1, parents, (1..=num_nodes).collect()).unwrap()
+    }
+
+    fn check_masks<M: IdealMask + std::fmt::Debug>(tree: &Tree) {
+        let (num_nodes, sequence_indices, jump_indices, _) = prep_args(tree, 0);
+        let toggle_masks = generate_toggle_masks::<M>(num_nodes, &jump_indices);
+        let mut ideal = ideal_mask::<M>(&sequence_indices);
+        let mut masks = vec![];
+        let _ = pop_jump_push_mask(&mut ideal, &toggle_masks, &mut |mask: &M| {
+            masks.push(*mask);
+            ControlFlow::Continue(())
+        });
+        let expected = sequential_ideals(tree)
+            .iter()
+            .map(|ideal| ideal_mask::<M>(ideal))
+            .collect::<Vec<_>>();
+        assert_eq!(masks, expected, "{tree:?}");
+        assert_eq!(ideal, M::EMPTY);
+    }
+
+    #[test]
+    fn masks_match_the_index_ideals() {
+        for tree in sample_trees() {
+            check_masks::<u64>(&tree);
+            check_masks::<u128>(&tree);
+        }
+        for num_nodes in [63, 64] {
+            check_masks::<u64>(&two_chains(num_nodes));
+        }
+        for num_nodes in [64, 65, 127, 128] {
+            check_masks::<u128>(&two_chains(num_nodes));
+        }
+    }
+
+    #[test]
+    fn iterator_matches_the_visits_and_stays_exhausted() {
+        for tree in sample_trees() {
+            let mut iterator = IdealIterator::new(&tree);
+            let mut ideals = vec![];
+            while let Some(ideal) = iterator.next_ideal() {
+                ideals.push(ideal.to_vec());
+            }
+            assert_eq!(ideals, sequential_ideals(&tree), "{tree:?}");
+            assert_eq!(iterator.next_ideal(), None);
+            assert_eq!(iterator.next_ideal(), None);
+        }
+    }
+
+    #[test]
+    fn breaking_stops_after_exactly_k_visits() {
+        for tree in sample_trees() {
+            let ideals = sequential_ideals(&tree);
+            let ks = [1, 2, ideals.len()];
+            for k in ks.into_iter().filter(|&k| k <= ideals.len()) {
+                let (num_nodes, mut sequence_indices, jump_indices, _) = prep_args(&tree, 0);
+                let mut count = 0;
+                let flow = pop_jump_push(
+                    num_nodes,
+                    &mut sequence_indices,
+                    &jump_indices,
+                    &mut |_: &[usize]| {
+                        count += 1;
+                        match count == k {
+                            true => ControlFlow::Break(()),
+                            false => ControlFlow::Continue(()),
+                        }
+                    },
+                );
+                assert!(flow.is_break());
+                assert_eq!(count, k);
+                assert_eq!(sequence_indices, ideals[k - 1], "{k} of {tree:?}");
+            }
+        }
+    }
 }
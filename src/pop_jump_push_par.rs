@@ -2,68 +2,259 @@
 //!
 
 use rayon::prelude::*;
-use std::time::Instant;
+use std::io::{BufWriter, Write};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
+use crate::index::compact_indices;
+use crate::index::IndexWidth;
+use crate::index::NodeIndex;
 use crate::node_manipulation::arrange_by_traversal_pre_order;
 use crate::node_manipulation::count_subtrees;
+use crate::node_manipulation::Tree;
+use crate::node_manipulation::TreeError;
+use crate::pop_jump_push;
 use crate::pop_jump_push::generate_jump_indices;
+use crate::pop_jump_push::WriterVisitor;
+use crate::rank::generate_suffix_counts;
+use crate::timing::time_runs;
+use crate::timing::try_time_runs;
+use crate::visitor::IdealVisitor;
+use crate::workers::assign_workers;
+use crate::workers::count_workers;
+use crate::workers::report_workers;
+use crate::workers::WorkerVisitor;
+use crate::workers::CHUNKS_PER_WORKER;
 
-pub(crate) struct ParArg {
+/// The number of visits between checks for idle workers needing a chunk split off.
+const SPLIT_INTERVAL: usize = 1 << 12;
+
+/// A chunk of the ideals and the worker it has been assigned to.
+pub struct ParArg {
     num_nodes: usize,
     sequence_indices: Vec<usize>,
     jump_indices: Vec<usize>,
     stop_index: usize,
     stop_value: usize,
     labels: Vec<usize>,
-    worker_id: u8,
-    output: u8,
+    worker_id: usize,
+    predicted_count: u128,
+}
+
+impl ParArg {
+    pub fn worker_id(&self) -> usize {
+        self.worker_id
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        /*!  - Returns the node labels indexed by pre-order index. */
+        &self.labels
+    }
+
+    pub fn sequence_indices(&self) -> &[usize] {
+        /*!  - Returns the worker's starting ideal. */
+        &self.sequence_indices
+    }
+
+    pub fn jump_indices(&self) -> &[usize] {
+        /*!  - Returns the pre-order jump indices. */
+        &self.jump_indices
+    }
+
+    pub fn stop_index(&self) -> usize {
+        self.stop_index
+    }
+
+    pub fn stop_value(&self) -> usize {
+        self.stop_value
+    }
+
+    pub fn predicted_count(&self) -> u128 {
+        /*!  - Returns the number of ideals in the chunk. */
+        self.predicted_count
+    }
 }
 
 struct WorkerDetail {
     sequence_indices: Vec<usize>,
     stop_index: usize,
     stop_value: usize,
-    worker_id: u8,
+    worker_id: usize,
+    predicted_count: u128,
 }
 
-fn pop_jump_push_par(arg_set: &ParArg) -> usize {
+pub fn pop_jump_push_par<I: NodeIndex, V: IdealVisitor<[I]>>(
+    arg_set: &ParArg,
+    visitor: &mut V,
+) -> ControlFlow<()> {
     /*!  - Implements the Pop Jump Push algorithm that works on chunks.
-     *  Loop terminates on the last ideal for the worker so it must be visited outside of the loop.
-     */
+
+    The chunk's indices are converted to any `NodeIndex` fitting `num_nodes` as `pop_jump_push`'s.
+    Loop terminates on the last ideal for the worker so it must be visited outside of the loop.
+    */
     let num_nodes = arg_set.num_nodes;
-    let mut sequence_indices = arg_set.sequence_indices.clone();
-    let jump_indices = arg_set.jump_indices.clone();
+    let mut sequence_indices = compact_indices::<I>(&arg_set.sequence_indices);
+    let jump_indices = compact_indices::<I>(&arg_set.jump_indices);
     let stop_index = arg_set.stop_index;
-    let stop_value = arg_set.stop_value;
-    let labels = &arg_set.labels;
-    let worker_id = arg_set.worker_id;
-    let output = arg_set.output;
+    let stop_value = I::from_usize(arg_set.stop_value);
 
-    let mut visited_count = 0;
     while sequence_indices.len() > stop_index && sequence_indices[stop_index] >= stop_value {
-        visited_count += visit(&sequence_indices, labels, worker_id, output);
-        let index = jump_indices[sequence_indices.pop().unwrap()];
+        visitor.visit(&sequence_indices)?;
+        let index = jump_indices[sequence_indices.pop().unwrap().to_usize()].to_usize();
         if index < num_nodes {
-            sequence_indices.extend(index..num_nodes);
+            sequence_indices.extend((index..num_nodes).map(I::from_usize));
         }
     }
-    visited_count + visit(&sequence_indices, labels, worker_id, output)
+    visitor.visit(&sequence_indices)
 }
 
-#[inline(always)]
-pub(crate) fn visit(ideal: &[usize], labels: &[usize], worker_id: u8, output: u8) -> usize {
-    /*!  -  Process/output ideals. */
-    // This just ensures the compiler doesn't optimize anything away during `output == 0` benchmarking.
-    let ideal = std::hint::black_box(ideal);
-
-    if output == 2 {
-        println!("{worker_id:<3}: {ideal:?}")
-    } else if output >= 3 {
-        let mut result = ideal.iter().map(|i| labels[*i]).collect::<Vec<_>>();
-        result.sort();
-        println!("{worker_id:<3}: {result:?}");
-    };
-    1
+pub fn visit_workers<I, V, F>(args: &[ParArg], make_visitor: F) -> Vec<V>
+where
+    I: NodeIndex,
+    V: IdealVisitor<[I]> + Send,
+    F: Fn(&ParArg) -> V + Sync,
+{
+    /*!  - Runs every chunk in parallel and returns the visitors in chunk order.
+
+    A visitor returning `ControlFlow::Break` stops its own worker and signals the remaining
+    workers to stop at their next visit.
+    */
+    let halt = AtomicBool::new(false);
+    args.par_iter()
+        .map(|arg_set| {
+            let mut visitor = HaltingVisitor {
+                visitor: make_visitor(arg_set),
+                halt: &halt,
+            };
+            let _ = pop_jump_push_par(arg_set, &mut visitor);
+            visitor.visitor
+        })
+        .collect()
+}
+
+pub fn visit_split<I, V, F>(num_nodes: usize, jump_indices: &[I], make_visitor: F) -> Vec<V>
+where
+    I: NodeIndex,
+    V: IdealVisitor<[I]> + Send,
+    F: Fn() -> V + Sync,
+{
+    /*!  - Runs Pop Jump Push splitting chunks on demand for idle workers to steal.
+
+    Runs on the current rayon thread pool and returns each chunk's visitor in Pop Jump Push order.
+    A chunk is a stack and a floor; it visits the ideals from the stack on that share the stack's
+    first `floor` indices. While fewer chunks are running or waiting than there are threads, a
+    chunk splits by excluding the node at its floor:
+    `[stack[..floor], jump_indices[stack[floor]]..]` becomes a new chunk with the same floor
+    while the running chunk raises its floor by one.
+    */
+    let halt = AtomicBool::new(false);
+    let busy = AtomicUsize::new(1);
+    let chunks = Mutex::new(vec![]);
+    rayon::scope(|scope| {
+        let chunk = Chunk {
+            num_nodes,
+            jump_indices,
+            make_visitor: &make_visitor,
+            halt: &halt,
+            busy: &busy,
+            num_threads: rayon::current_num_threads(),
+            chunks: &chunks,
+        };
+        chunk.visit(scope, (0..num_nodes).map(I::from_usize).collect(), 1);
+    });
+    let mut chunks = chunks.into_inner().unwrap();
+    chunks.sort_by(|a, b| a.0.cmp(&b.0));
+    chunks.into_iter().map(|(_, visitor)| visitor).collect()
+}
+
+/// The state shared by every chunk of a `visit_split` run.
+struct Chunk<'a, I, V, F> {
+    num_nodes: usize,
+    jump_indices: &'a [I],
+    make_visitor: &'a F,
+    halt: &'a AtomicBool,
+    busy: &'a AtomicUsize,
+    num_threads: usize,
+    chunks: &'a Mutex<Vec<(Vec<usize>, V)>>,
+}
+
+impl<'a, I, V, F> Chunk<'a, I, V, F>
+where
+    I: NodeIndex,
+    V: IdealVisitor<[I]> + Send,
+    F: Fn() -> V + Sync,
+{
+    fn visit<'s>(&self, scope: &rayon::Scope<'s>, mut sequence_indices: Vec<I>, floor: usize)
+    where
+        'a: 's,
+    {
+        /*!  - Visits the chunk's ideals, spawning split off chunks into the scope. */
+        // Chunks are ordered by their first ideal where a prefix comes after its extensions.
+        let mut order_key = sequence_indices
+            .iter()
+            .map(|index| index.to_usize())
+            .collect::<Vec<_>>();
+        order_key.push(usize::MAX);
+        let mut visitor = HaltingVisitor {
+            visitor: (self.make_visitor)(),
+            halt: self.halt,
+        };
+        let mut floor = floor;
+        let mut count = 0usize;
+        loop {
+            // Popping an index below the floor would leave the chunk.
+            if visitor.visit(&sequence_indices).is_break() || sequence_indices.len() == floor {
+                break;
+            }
+            let index = self.jump_indices[sequence_indices.pop().unwrap().to_usize()].to_usize();
+            if index < self.num_nodes {
+                sequence_indices.extend((index..self.num_nodes).map(I::from_usize));
+            }
+            count += 1;
+            if count.is_multiple_of(SPLIT_INTERVAL)
+                && sequence_indices.len() > floor
+                && self.busy.load(Ordering::Relaxed) < self.num_threads
+            {
+                self.busy.fetch_add(1, Ordering::Relaxed);
+                let index = self.jump_indices[sequence_indices[floor].to_usize()].to_usize();
+                let split_indices = sequence_indices[..floor]
+                    .iter()
+                    .cloned()
+                    .chain((index..self.num_nodes).map(I::from_usize))
+                    .collect::<Vec<_>>();
+                let split_floor = floor;
+                let chunk = Chunk { ..*self };
+                scope.spawn(move |scope| chunk.visit(scope, split_indices, split_floor));
+                floor += 1;
+            }
+        }
+        self.busy.fetch_sub(1, Ordering::Relaxed);
+        self.chunks
+            .lock()
+            .unwrap()
+            .push((order_key, visitor.visitor));
+    }
+}
+
+struct HaltingVisitor<'a, V> {
+    visitor: V,
+    halt: &'a AtomicBool,
+}
+
+impl<Ideal: ?Sized, V: IdealVisitor<Ideal>> IdealVisitor<Ideal> for HaltingVisitor<'_, V> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &Ideal) -> ControlFlow<()> {
+        if self.halt.load(Ordering::Relaxed) {
+            return ControlFlow::Break(());
+        }
+        let flow = self.visitor.visit(ideal);
+        if flow.is_break() {
+            self.halt.store(true, Ordering::Relaxed);
+        }
+        flow
+    }
 }
 
 fn get_pop_jump_push_ideals(
@@ -83,19 +274,18 @@ fn get_pop_jump_push_ideals(
     ideals
 }
 
-pub(crate) fn prep_args(
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
-    output: u8,
-    max_workers: u8,
-) -> Vec<ParArg> {
+pub fn prep_args(tree: &Tree, output: u8, max_workers: usize) -> Result<Vec<ParArg>, TreeError> {
     /*!  -  Return a tuple of the arguments for pop_jump_push_par. */
-    let num_nodes = children.len();
-    let (parents, children) = arrange_by_traversal_pre_order(root, parents, children);
+    // The chunks are prefixes of ideals that all include the root.
+    if tree.root() == 0 {
+        return Err(TreeError::Forest);
+    }
+    let num_nodes = tree.len();
+    let (parents, children) =
+        arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
     let labels = children.clone();
     let jump_indices = generate_jump_indices(&parents, &children);
-    let worker_details = get_worker_details(&jump_indices, max_workers as usize);
+    let worker_details = get_worker_details(&jump_indices, max_workers);
 
     let args: Vec<ParArg> = worker_details
         .iter()
@@ -107,7 +297,7 @@ pub(crate) fn prep_args(
             stop_value: wd.stop_value,
             labels: labels.clone(),
             worker_id: wd.worker_id,
-            output,
+            predicted_count: wd.predicted_count,
         })
         .collect();
 
@@ -117,103 +307,290 @@ pub(crate) fn prep_args(
         let arg = "jump_indices";
         println!("{arg:>18}: {jump_indices:?}");
 
-        println!("\t*** {:?} workers ***", args.len());
-        for arg_set in args.iter() {
-            let arg = ["Worker ", &arg_set.worker_id.to_string()].concat();
+        let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+        println!("\t*** {} chunks for {num_workers} workers ***", args.len());
+        for (chunk_id, arg_set) in args.iter().enumerate() {
+            let arg = ["Chunk ", &chunk_id.to_string()].concat();
             print!("{arg:>18}: ");
+            let arg = arg_set.worker_id;
+            print!("worker: {arg:>3}");
             let arg = arg_set.stop_index;
-            print!("stop_index: {arg:>3}");
+            print!(" stop_index: {arg:>3}");
             let arg = arg_set.stop_value;
-            println!(" stop_value: {arg:>3}");
+            print!(" stop_value: {arg:>3}");
+            let arg = arg_set.predicted_count;
+            println!(" predicted: {arg}");
             let arg = "";
             println!("{arg:>18}  indices: {:?} ", &arg_set.sequence_indices);
         }
     }
 
-    args
+    Ok(args)
 }
 
-pub(crate) fn pop_jump_push_par_main(
-    root: usize,
-    parents: &[usize],
-    children: &Vec<usize>,
+#[doc(hidden)]
+pub fn pop_jump_push_par_main(
+    tree: &Tree,
     output: u8,
     reps: u32,
-    max_workers: u8,
-) {
+    max_workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
     /*! Rust doesn't have stable generators so the whole tree gets processed with 'visits'. */
-    let args = prep_args(root, parents, children, output, max_workers);
+    let args = prep_args(tree, output, max_workers)?;
 
-    let num_nodes = children.len();
-    let num_workers = args.len();
-    let ideals_count = count_subtrees(children[0], parents, children);
+    let num_nodes = tree.len();
+    let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+    let mut worker_args = vec![vec![]; num_workers];
+    for arg_set in args.iter() {
+        worker_args[arg_set.worker_id].push(arg_set);
+    }
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
     let ttl_ideals = ideals_count as f64 * reps as f64;
     print!("Generating {ideals_count} ideals from {num_nodes} nodes using ");
-    println!("{num_workers} workers {reps} times ({ttl_ideals}).\n");
+    println!(
+        "{num_workers} workers for {} chunks {reps} times ({ttl_ideals}).\n",
+        args.len()
+    );
 
+    // The chunks run on the smallest index type fitting `num_nodes`.
+    let width = IndexWidth::fitting(num_nodes);
     let mut performance_data = vec![];
-    let mut best_rep_time_delta = f64::MAX;
-    let overall_start_time = Instant::now();
-    for _ in 0..reps {
-        let rep_start_time = Instant::now();
-        performance_data = args
-            .par_iter()
-            .map(|arg_set| {
-                let start_time = Instant::now();
-                let ideals_count = pop_jump_push_par(arg_set);
-                let delta = start_time.elapsed();
-                (arg_set.worker_id, delta, ideals_count)
-            })
-            .collect::<Vec<_>>();
-        let rep_time_delta = rep_start_time.elapsed().as_secs_f64();
-        if rep_time_delta < best_rep_time_delta {
-            best_rep_time_delta = rep_time_delta
-        }
-    }
-    let overall_time_delta = overall_start_time.elapsed().as_secs_f64();
-
-    println!("\tCompleted generating ideals...");
-    println!(
-        "\tAvg Duration per tree {}",
-        overall_time_delta / reps as f64
+    let timing = time_runs(
+        reps,
+        || (),
+        |()| {
+            performance_data = match width {
+                IndexWidth::U8 => run_workers::<u8>(&worker_args, output),
+                IndexWidth::U16 => run_workers::<u16>(&worker_args, output),
+                IndexWidth::U32 => run_workers::<u32>(&worker_args, output),
+                IndexWidth::Usize => run_workers::<usize>(&worker_args, output),
+            };
+        },
     );
-    println!("\tBest Duration per tree {best_rep_time_delta}");
+    timing.report(ideals_count, "ideals", "ideal");
+
+    report_workers(&performance_data);
+    Ok(())
+}
+
+fn run_workers<I: NodeIndex>(
+    worker_args: &[Vec<&ParArg>],
+    output: u8,
+) -> Vec<(usize, Duration, usize, u128)> {
+    /*!  - Runs each worker's chunks with `I` indices, returning its time, count and prediction. */
+    worker_args
+        .par_iter()
+        .enumerate()
+        .map(|(worker_id, chunks)| {
+            let mut visitor = WorkerVisitor {
+                labels: &chunks[0].labels,
+                worker_id,
+                output,
+                count: 0,
+                format: pop_jump_push::format_ideal::<I>,
+            };
+            let start_time = Instant::now();
+            for arg_set in chunks.iter() {
+                let _ = pop_jump_push_par(arg_set, &mut visitor);
+            }
+            let delta = start_time.elapsed();
+            let predicted_count: u128 = chunks.iter().map(|c| c.predicted_count).sum();
+            (worker_id, delta, visitor.count, predicted_count)
+        })
+        .collect()
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_par_ordered_main(
+    tree: &Tree,
+    output: u8,
+    reps: u32,
+    max_workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    /*! Processes the tree with 'visits', writing the ideals to stdout in the sequential order. */
+    let args = prep_args(tree, output, max_workers)?;
+
+    let num_nodes = tree.len();
+    let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+    let ideals_count = count_subtrees(tree.root(), tree.parents(), tree.children())?;
+    let ttl_ideals = ideals_count as f64 * reps as f64;
+    print!("Generating {ideals_count} ideals in order from {num_nodes} nodes using ");
     println!(
-        "\t{} ns avg per ideal",
-        (overall_time_delta / reps as f64) / ideals_count as f64 * 1e9
+        "{num_workers} workers for {} chunks {reps} times ({ttl_ideals}).\n",
+        args.len()
     );
-    println!(
-        "\t{} ns best per ideal\n",
-        best_rep_time_delta / ideals_count as f64 * 1e9
+
+    let width = IndexWidth::fitting(num_nodes);
+    let timing = try_time_runs(
+        reps,
+        || BufWriter::new(std::io::stdout()),
+        |mut stdout| {
+            match width {
+                IndexWidth::U8 => write_ordered::<u8>(&args, num_workers, output, &mut stdout)?,
+                IndexWidth::U16 => write_ordered::<u16>(&args, num_workers, output, &mut stdout)?,
+                IndexWidth::U32 => write_ordered::<u32>(&args, num_workers, output, &mut stdout)?,
+                IndexWidth::Usize => {
+                    write_ordered::<usize>(&args, num_workers, output, &mut stdout)?
+                }
+            }
+            stdout.flush()
+        },
+    )?;
+    timing.report(ideals_count, "ideals", "ideal");
+    Ok(())
+}
+
+pub fn write_ordered<I: NodeIndex>(
+    args: &[ParArg],
+    num_workers: usize,
+    output: u8,
+    writer: &mut (impl Write + Send),
+) -> std::io::Result<()> {
+    /*!  - Writes the chunks' ideals as `WriterVisitor` does, in the sequential order.
+
+    The chunks cover consecutive runs of the sequential order so each window of num_workers
+    chunks runs in parallel. The window's first chunk writes straight to writer while the later
+    chunks are buffered, to be written out in chunk order once the first completes. So at most
+    num_workers - 1 chunks are held in memory at a time, the text of the `predicted_count` ideals
+    of each; with `prep_args` cutting about `CHUNKS_PER_WORKER` chunks per worker that is roughly
+    1 / `CHUNKS_PER_WORKER` of the whole output for evenly sized chunks.
+    */
+    for window in args.chunks(std::cmp::max(num_workers, 1)) {
+        let (error, buffers) = rayon::join(
+            || {
+                let mut visitor = WriterVisitor {
+                    labels: &window[0].labels,
+                    output,
+                    writer: &mut *writer,
+                    count: 0,
+                    error: None,
+                };
+                let _ = pop_jump_push_par::<I, _>(&window[0], &mut visitor);
+                visitor.error
+            },
+            || {
+                window[1..]
+                    .par_iter()
+                    .map(|arg_set| {
+                        let mut visitor = WriterVisitor {
+                            labels: &arg_set.labels,
+                            output,
+                            writer: vec![],
+                            count: 0,
+                            error: None,
+                        };
+                        let _ = pop_jump_push_par::<I, _>(arg_set, &mut visitor);
+                        visitor.writer
+                    })
+                    .collect::<Vec<_>>()
+            },
+        );
+        if let Some(e) = error {
+            return Err(e);
+        }
+        for buffer in buffers {
+            writer.write_all(&buffer)?;
+        }
+    }
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_split_main(
+    tree: &Tree,
+    output: u8,
+    reps: u32,
+    max_workers: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    /*! Processes the tree with 'visits' using max_workers threads that split chunks on demand. */
+    // Every chunk's floor is at least 1 so the root is always included.
+    if tree.root() == 0 {
+        return Err(Box::new(TreeError::<usize>::Forest));
+    }
+    let num_nodes = tree.len();
+    let (parents, labels) =
+        arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
+    let jump_indices = generate_jump_indices(&parents, &labels);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_workers)
+        .build()?;
+    let ideals_count = count_subtrees(tree.root(), &parents, &labels)?;
+    let ttl_ideals = ideals_count as f64 * reps as f64;
+    print!("Generating {ideals_count} ideals from {num_nodes} nodes using ");
+    println!("{max_workers} workers splitting on demand {reps} times ({ttl_ideals}).\n");
+    if output == 1 {
+        let arg = "num_nodes";
+        println!("{arg:>18}: {num_nodes}");
+        let arg = "jump_indices";
+        println!("{arg:>18}: {jump_indices:?}");
+    }
+
+    let width = IndexWidth::fitting(num_nodes);
+    let mut chunk_counts = vec![];
+    let timing = time_runs(
+        reps,
+        || (),
+        |()| {
+            chunk_counts = pool.install(|| match width {
+                IndexWidth::U8 => split_workers::<u8>(num_nodes, &jump_indices, &labels, output),
+                IndexWidth::U16 => split_workers::<u16>(num_nodes, &jump_indices, &labels, output),
+                IndexWidth::U32 => split_workers::<u32>(num_nodes, &jump_indices, &labels, output),
+                IndexWidth::Usize => {
+                    split_workers::<usize>(num_nodes, &jump_indices, &labels, output)
+                }
+            });
+        },
     );
+    timing.report(ideals_count, "ideals", "ideal");
 
     println!("\tWorkers summary for the last rep...");
-    performance_data.sort_by_key(|x| x.0);
-    for (id, delta, ideal_count) in performance_data.iter() {
-        let delta = delta.as_secs_f64();
-        println!(
-            "\t\tworker {id:<3} generated {ideal_count} ideals in {delta} for {:?} (ns)",
-            delta / *ideal_count as f64 * 1e9,
-        )
+    let mut worker_totals = vec![(0, 0); max_workers];
+    for &(worker_id, count) in chunk_counts.iter() {
+        let (chunks_count, ideal_count) = &mut worker_totals[worker_id];
+        *chunks_count += 1;
+        *ideal_count += count;
     }
-    let generated_count: usize = performance_data
-        .iter()
-        .map(|(_, _, ideal_count)| ideal_count)
-        .sum();
-    println!("\t\tWorkers    generated {generated_count} ideals.\n");
+    for (id, (chunks_count, ideal_count)) in worker_totals.iter().enumerate() {
+        println!("\t\tworker {id:<3} generated {ideal_count} ideals in {chunks_count} chunks");
+    }
+    let generated_count: usize = chunk_counts.iter().map(|(_, count)| count).sum();
+    let num_chunks = chunk_counts.len();
+    println!("\t\tWorkers    generated {generated_count} ideals in {num_chunks} chunks.\n");
+    Ok(())
+}
+
+fn split_workers<I: NodeIndex>(
+    num_nodes: usize,
+    jump_indices: &[usize],
+    labels: &[usize],
+    output: u8,
+) -> Vec<(usize, usize)> {
+    /*!  - Runs `visit_split` with `I` indices, returning each chunk's worker and ideal count. */
+    let jump_indices = compact_indices::<I>(jump_indices);
+    let visitors = visit_split(num_nodes, &jump_indices, || WorkerVisitor {
+        labels,
+        worker_id: rayon::current_thread_index().unwrap(),
+        output,
+        count: 0,
+        format: pop_jump_push::format_ideal::<I>,
+    });
+    visitors
+        .into_iter()
+        .map(|visitor| (visitor.worker_id, visitor.count))
+        .collect()
 }
 
 fn generate_worker_ideal_prefixes(jump_indices: &[usize], num_workers: usize) -> Vec<Vec<usize>> {
     /*  - Returns the ideal prefixes for each worker
     Consume leading indices in the pre-ordered tree.
     Use the distinct subtrees from them as worker prefixes.
-    Limit nodes used to those that generate <= max_num_workers distinct prefixes.
+    Limit nodes used to those that generate <= num_workers distinct prefixes.
     */
     let num_nodes = jump_indices.len();
-    let max_num_workers = *vec![16, num_nodes / 2, num_workers].iter().min().unwrap();
+    let max_num_workers = std::cmp::max(num_workers, 1);
 
     let mut ideal_prefixes = Vec::new();
-    for n in 1..=max_num_workers {
+    for n in 1..=num_nodes {
         let mut sequence_indices = (0..n).collect::<Vec<_>>();
         let prefixes = get_pop_jump_push_ideals(n, &mut sequence_indices, &jump_indices[0..n])
             .into_iter()
@@ -229,9 +606,17 @@ fn generate_worker_ideal_prefixes(jump_indices: &[usize], num_workers: usize) ->
 }
 
 fn get_worker_details(jump_indices: &[usize], num_workers: usize) -> Vec<WorkerDetail> {
-    /*! - Returns worker details controlling which ideals a worker generates. */
+    /*!  - Returns worker details controlling which ideals a worker generates.
 
-    let ideal_prefixes = generate_worker_ideal_prefixes(jump_indices, num_workers);
+    The tree is cut into about CHUNKS_PER_WORKER chunks per worker. Every node from a chunk's
+    stop_value on has an included parent so the chunk holds exactly `F(stop_value)` ideals (see
+    `rank`). Chunks are handed out largest first to the worker with the fewest predicted ideals.
+    */
+    let num_chunks = num_workers.saturating_mul(CHUNKS_PER_WORKER);
+    let ideal_prefixes = generate_worker_ideal_prefixes(jump_indices, num_chunks);
+    // Only trees with more than u128::MAX ideals overflow, leaving every chunk the same size.
+    let suffix_counts = generate_suffix_counts(jump_indices)
+        .unwrap_or_else(|_| vec![u128::MAX; jump_indices.len() + 1]);
 
     // The first prefix contains all of the leading nodes.
     // Generation is done in reverse pre-order.
@@ -246,7 +631,7 @@ fn get_worker_details(jump_indices: &[usize], num_workers: usize) -> Vec<WorkerD
     let num_nodes = jump_indices.len();
     let tree_indices = (0..num_nodes).collect::<Vec<_>>();
     let mut worker_details = vec![];
-    for (worker_id, prefix) in ideal_prefixes.iter().enumerate() {
+    for prefix in ideal_prefixes.iter() {
         let deactivated_nodes = base_prefix_indices
             .iter()
             .filter(|i| !prefix.contains(i))
@@ -273,9 +658,202 @@ fn get_worker_details(jump_indices: &[usize], num_workers: usize) -> Vec<WorkerD
             sequence_indices,
             stop_index,
             stop_value,
-            worker_id: worker_id.try_into().unwrap(),
+            worker_id: 0,
+            predicted_count: suffix_counts[stop_value],
         });
     }
 
+    let predicted_counts = worker_details
+        .iter()
+        .map(|wd| wd.predicted_count)
+        .collect::<Vec<_>>();
+    let worker_ids = assign_workers(&predicted_counts, num_workers);
+    for (wd, worker_id) in worker_details.iter_mut().zip(worker_ids) {
+        wd.worker_id = worker_id;
+    }
+
     worker_details
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::test_support::random_tree;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sequential_ideals;
+
+    /// Collects the visited ideals as `usize` indices.
+    #[derive(Default)]
+    struct CollectVisitor(Vec<Vec<usize>>);
+
+    impl<I: NodeIndex> IdealVisitor<[I]> for CollectVisitor {
+        fn visit(&mut self, ideal: &[I]) -> ControlFlow<()> {
+            self.0
+                .push(ideal.iter().map(|index| index.to_usize()).collect());
+            ControlFlow::Continue(())
+        }
+    }
+
+    /// Counts the visits of every worker together, breaking once `limit` have been made.
+    struct LimitVisitor<'a> {
+        visits: &'a AtomicUsize,
+        limit: usize,
+        count: usize,
+    }
+
+    impl IdealVisitor for LimitVisitor<'_> {
+        fn visit(&mut self, _: &[usize]) -> ControlFlow<()> {
+            self.count += 1;
+            match self.visits.fetch_add(1, Ordering::Relaxed) + 1 >= self.limit {
+                true => ControlFlow::Break(()),
+                false => ControlFlow::Continue(()),
+            }
+        }
+    }
+
+    fn test_trees() -> Vec<Tree> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut trees = sample_trees();
+        trees.push(random_tree(&mut rng, 24, false));
+        trees.into_iter().filter(|tree| tree.root() != 0).collect()
+    }
+
+    #[test]
+    fn chunks_concatenate_to_the_sequential_order() {
+        for tree in test_trees() {
+            let ideals = sequential_ideals(&tree);
+            for max_workers in [1, 2, 3, 8] {
+                let args = prep_args(&tree, 0, max_workers).unwrap();
+                let visitors = visit_workers::<usize, _, _>(&args, |_| CollectVisitor::default());
+                let mut chunk_ideals = vec![];
+                for (arg_set, visitor) in args.iter().zip(visitors) {
+                    assert!(arg_set.worker_id() < max_workers);
+                    assert_eq!(visitor.0.len() as u128, arg_set.predicted_count());
+                    chunk_ideals.extend(visitor.0);
+                }
+                assert_eq!(chunk_ideals, ideals, "{max_workers} workers for {tree:?}");
+
+                let visitors = visit_workers::<u8, _, _>(&args, |_| CollectVisitor::default());
+                let chunk_ideals = visitors
+                    .into_iter()
+                    .flat_map(|visitor| visitor.0)
+                    .collect::<Vec<_>>();
+                assert_eq!(chunk_ideals, ideals, "u8 indices for {tree:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn forests_have_no_chunks() {
+        for tree in sample_trees().iter().filter(|tree| tree.root() == 0) {
+            assert!(matches!(prep_args(tree, 0, 4), Err(TreeError::Forest)));
+        }
+    }
+
+    #[test]
+    fn split_chunks_concatenate_to_the_sequential_order() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        for tree in test_trees() {
+            let (num_nodes, _, jump_indices, _) = crate::pop_jump_push::prep_args(&tree, 0);
+            let visitors =
+                pool.install(|| visit_split(num_nodes, &jump_indices, CollectVisitor::default));
+            let ideals = sequential_ideals(&tree);
+            // The first split is due once the only chunk reaches SPLIT_INTERVAL visits.
+            assert_eq!(visitors.len() > 1, ideals.len() > SPLIT_INTERVAL + 1);
+            let chunk_ideals = visitors
+                .into_iter()
+                .flat_map(|visitor| visitor.0)
+                .collect::<Vec<_>>();
+            assert_eq!(chunk_ideals, ideals, "{tree:?}");
+
+            let jump_indices = compact_indices::<u8>(&jump_indices);
+            let visitors =
+                pool.install(|| visit_split(num_nodes, &jump_indices, CollectVisitor::default));
+            let chunk_ideals = visitors
+                .into_iter()
+                .flat_map(|visitor| visitor.0)
+                .collect::<Vec<_>>();
+            assert_eq!(chunk_ideals, ideals, "u8 indices for {tree:?}");
+        }
+    }
+
+    #[test]
+    fn ordered_writes_match_the_sequential_writes() {
+        for tree in test_trees() {
+            for output in [2, 4] {
+                let (num_nodes, mut sequence_indices, jump_indices, labels) =
+                    crate::pop_jump_push::prep_args(&tree, 0);
+                let mut visitor = WriterVisitor {
+                    labels: &labels,
+                    output,
+                    writer: vec![],
+                    count: 0,
+                    error: None,
+                };
+                let _ = pop_jump_push::pop_jump_push(
+                    num_nodes,
+                    &mut sequence_indices,
+                    &jump_indices,
+                    &mut visitor,
+                );
+                for max_workers in [1, 3, 8] {
+                    let args = prep_args(&tree, 0, max_workers).unwrap();
+                    let num_workers = count_workers(args.iter().map(|a| a.worker_id));
+                    let mut written = vec![];
+                    write_ordered::<usize>(&args, num_workers, output, &mut written).unwrap();
+                    assert_eq!(
+                        written, visitor.writer,
+                        "{max_workers} workers for {tree:?}"
+                    );
+                    let mut written = vec![];
+                    write_ordered::<u8>(&args, num_workers, output, &mut written).unwrap();
+                    assert_eq!(written, visitor.writer, "u8 indices for {tree:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn breaking_halts_every_worker() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let tree = random_tree(&mut rng, 24, false);
+        let args = prep_args(&tree, 0, 4).unwrap();
+        assert!(args.len() > 2);
+        let first_chunk = args[0].predicted_count() as usize;
+        for num_threads in [1, 4] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(num_threads)
+                .build()
+                .unwrap();
+            for k in [1, 2, first_chunk, first_chunk + 1] {
+                let visits = AtomicUsize::new(0);
+                let visitors = pool.install(|| {
+                    visit_workers::<usize, _, _>(&args, |_| LimitVisitor {
+                        visits: &visits,
+                        limit: k,
+                        count: 0,
+                    })
+                });
+                let total = visitors.iter().map(|visitor| visitor.count).sum::<usize>();
+                assert_eq!(total, visits.load(Ordering::Relaxed));
+                // Workers already past the halt check when the limit is reached finish that visit.
+                assert!((k..k + num_threads).contains(&total), "{total} for {k}");
+                if num_threads == 1 {
+                    let counts = visitors.iter().map(|visitor| visitor.count);
+                    let mut remaining = k;
+                    for (arg_set, count) in args.iter().zip(counts) {
+                        let expected = remaining.min(arg_set.predicted_count() as usize);
+                        assert_eq!(count, expected);
+                        remaining -= expected;
+                    }
+                }
+            }
+        }
+    }
+}
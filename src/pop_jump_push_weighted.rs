@@ -0,0 +1,323 @@
+//! # Weighted implementation of the Pop Jump Push Algorithm for generating all ideals of an arborescence.
+//!
+//! A running weight is maintained alongside the pop, jump and push steps so each ideal's total
+//! weight is available without iterating over the visited indices. Every push is a pre-order
+//! suffix `[i..n)` so its weight is a single lookup into the suffix sums of the node weights.
+//!
+//! With non-negative weights the budgeted variant only visits ideals whose weight is within the
+//! budget; pushes that would exceed it are truncated by jumping over the subtrees that don't fit.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::{Add, ControlFlow, Sub};
+
+use crate::node_manipulation::arrange_by_traversal_pre_order;
+use crate::node_manipulation::Tree;
+use crate::pop_jump_push::generate_jump_indices;
+use crate::timing::time_runs;
+use crate::visitor::WeightedIdealVisitor;
+
+/// Numeric types usable as node weights (`i64`, `f64`, ...); `Default` is taken as zero.
+pub trait Weight: Copy + Default + PartialOrd + Add<Output = Self> + Sub<Output = Self> {}
+
+impl<W> Weight for W where W: Copy + Default + PartialOrd + Add<Output = W> + Sub<Output = W> {}
+
+/// The `num_nodes`, `sequence_indices`, `jump_indices`, pre-order `weights` and `suffix_weights`
+/// arguments for calling pop_jump_push_weighted followed by the pre-order node `labels`.
+pub type WeightedArgs<W> = (usize, Vec<usize>, Vec<usize>, Vec<W>, Vec<W>, Vec<usize>);
+
+pub fn pop_jump_push_weighted<W: Weight, V: WeightedIdealVisitor<W>>(
+    num_nodes: usize,
+    sequence_indices: &mut Vec<usize>,
+    jump_indices: &[usize],
+    weights: &[W],
+    suffix_weights: &[W],
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm passing each ideal's total weight to the visitor.
+
+    Returns `ControlFlow::Break` if the visitor stopped the enumeration, in which case
+    `sequence_indices` still holds the last visited ideal.
+    */
+    let mut weight = sequence_indices
+        .iter()
+        .fold(W::default(), |w, &i| w + weights[i]);
+    while !sequence_indices.is_empty() {
+        visitor.visit(sequence_indices, weight)?;
+        let index = sequence_indices.pop().unwrap();
+        weight = weight - weights[index];
+        let index = jump_indices[index];
+        if index < num_nodes {
+            sequence_indices.extend(index..num_nodes);
+            weight = weight + suffix_weights[index];
+        }
+    }
+    ControlFlow::Continue(())
+}
+
+pub fn pop_jump_push_budgeted<W: Weight, V: WeightedIdealVisitor<W>>(
+    num_nodes: usize,
+    jump_indices: &[usize],
+    weights: &[W],
+    suffix_weights: &[W],
+    budget: W,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Implements the Pop Jump Push algorithm visiting only ideals with weight <= budget.
+
+    Weights must be non-negative. Instead of pushing all of `[i..n)` the push takes each node in
+    turn when it fits within the budget and otherwise jumps over the node's subtree. The whole
+    range is still pushed at once whenever its suffix weight fits.
+    */
+    let mut sequence_indices = Vec::with_capacity(num_nodes);
+    let mut weight = W::default();
+    let mut index = 0;
+    loop {
+        while index < num_nodes {
+            if weight + suffix_weights[index] <= budget {
+                sequence_indices.extend(index..num_nodes);
+                weight = weight + suffix_weights[index];
+                break;
+            }
+            if weight + weights[index] <= budget {
+                sequence_indices.push(index);
+                weight = weight + weights[index];
+                index += 1;
+            } else {
+                index = jump_indices[index];
+            }
+        }
+        if sequence_indices.is_empty() {
+            return ControlFlow::Continue(());
+        }
+        visitor.visit(&sequence_indices, weight)?;
+        let last = sequence_indices.pop().unwrap();
+        weight = weight - weights[last];
+        index = jump_indices[last];
+    }
+}
+
+/// Prints ideals and their weights according to the cli `output` setting and counts them.
+#[doc(hidden)]
+pub struct WeightedOutputVisitor<'a> {
+    pub labels: &'a [usize],
+    pub output: u8,
+    pub count: usize,
+}
+
+impl<W: Display> WeightedIdealVisitor<W> for WeightedOutputVisitor<'_> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[usize], weight: W) -> ControlFlow<()> {
+        // This just ensures the compiler doesn't optimize anything away during `output == 0` benchmarking.
+        let ideal = std::hint::black_box(ideal);
+
+        if self.output == 2 {
+            println!("{ideal:?}: {weight}")
+        } else if self.output >= 3 {
+            let mut result = ideal.iter().map(|i| self.labels[*i]).collect::<Vec<_>>();
+            result.sort();
+            println!("{result:?}: {weight}");
+        };
+        self.count += 1;
+        ControlFlow::Continue(())
+    }
+}
+
+pub fn prep_args<W: Weight + std::fmt::Debug>(
+    tree: &Tree,
+    weights: &[W],
+    output: u8,
+) -> WeightedArgs<W> {
+    /*!  -  Return a tuple of the arguments for calling pop_jump_push_weighted.
+
+    The weights are given in the same order as the children.
+    */
+    let num_nodes = tree.len();
+    let sequence_indices = (0..num_nodes).collect::<Vec<_>>();
+    let weight_indices: HashMap<usize, usize> = tree
+        .children()
+        .iter()
+        .enumerate()
+        .map(|(i, &c)| (c, i))
+        .collect();
+    let (parents, children) =
+        arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
+    let jump_indices = generate_jump_indices(&parents, &children);
+    let weights = children
+        .iter()
+        .map(|c| weights[weight_indices[c]])
+        .collect::<Vec<_>>();
+    let suffix_weights = generate_suffix_weights(&weights);
+    if output == 1 {
+        let arg = "num_nodes";
+        println!("{arg:>18}: {num_nodes}");
+        let arg = "sequence_indices";
+        println!("{arg:>18}: {sequence_indices:?}");
+        let arg = "jump_indices";
+        println!("{arg:>18}: {jump_indices:?}");
+        let arg = "weights";
+        println!("{arg:>18}: {weights:?}");
+        let arg = "suffix_weights";
+        println!("{arg:>18}: {suffix_weights:?}");
+    }
+    (
+        num_nodes,
+        sequence_indices,
+        jump_indices,
+        weights,
+        suffix_weights,
+        children,
+    )
+}
+
+pub fn generate_suffix_weights<W: Weight>(weights: &[W]) -> Vec<W> {
+    /*!  - Returns the total weight of each pre-order suffix `[i..n)` including the empty `[n..n)`. */
+    let mut suffix_weights = vec![W::default(); weights.len() + 1];
+    for index in (0..weights.len()).rev() {
+        suffix_weights[index] = suffix_weights[index + 1] + weights[index];
+    }
+    suffix_weights
+}
+
+#[doc(hidden)]
+pub fn pop_jump_push_budgeted_main<W: Weight + Display + std::fmt::Debug>(
+    tree: &Tree,
+    weights: &[W],
+    budget: W,
+    output: u8,
+    reps: u32,
+) {
+    /*! Times reps runs of `pop_jump_push_budgeted`, printing the ideals within budget per output.
+
+    The number of ideals within the budget isn't known up front so the runs count them.
+    */
+    let num_nodes = tree.len();
+    println!("Generating ideals with weight <= {budget} from {num_nodes} nodes {reps} times.\n");
+
+    let (num_nodes, _, jump_indices, weights, suffix_weights, labels) =
+        prep_args(tree, weights, output);
+
+    let mut ideals_count = 0;
+    let timing = time_runs(
+        reps,
+        || WeightedOutputVisitor {
+            labels: &labels,
+            output,
+            count: 0,
+        },
+        |mut visitor| {
+            let _ = pop_jump_push_budgeted(
+                num_nodes,
+                &jump_indices,
+                &weights,
+                &suffix_weights,
+                budget,
+                &mut visitor,
+            );
+            ideals_count = visitor.count;
+        },
+    );
+    timing.report(
+        ideals_count as u128,
+        &format!("{ideals_count} ideals"),
+        "ideal",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sorted_labels;
+
+    fn random_weights(tree: &Tree, rng: &mut StdRng) -> (Vec<i64>, HashMap<usize, i64>) {
+        let weights = (0..tree.len())
+            .map(|_| rng.gen_range(0..4))
+            .collect::<Vec<_>>();
+        let weight_of = tree
+            .children()
+            .iter()
+            .cloned()
+            .zip(weights.clone())
+            .collect();
+        (weights, weight_of)
+    }
+
+    #[test]
+    fn weighted_ideals_have_their_total_weight() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for tree in sample_trees() {
+            let (weights, weight_of) = random_weights(&tree, &mut rng);
+            let (num_nodes, mut sequence_indices, jump_indices, weights, suffix_weights, labels) =
+                prep_args(&tree, &weights, 0);
+            let mut ideals = BTreeSet::new();
+            let _ = pop_jump_push_weighted(
+                num_nodes,
+                &mut sequence_indices,
+                &jump_indices,
+                &weights,
+                &suffix_weights,
+                &mut |ideal: &[usize], weight| {
+                    ideals.insert((sorted_labels(ideal, &labels), weight));
+                    ControlFlow::Continue(())
+                },
+            );
+            let expected = brute_force_ideals(&tree)
+                .into_iter()
+                .map(|ideal| {
+                    let weight = ideal.iter().map(|node| weight_of[node]).sum();
+                    (ideal, weight)
+                })
+                .collect::<BTreeSet<_>>();
+            assert_eq!(ideals, expected, "{tree:?}");
+        }
+    }
+
+    #[test]
+    fn budgeted_ideals_are_the_ideals_within_budget_in_order() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for tree in sample_trees() {
+            let (weights, _) = random_weights(&tree, &mut rng);
+            let (num_nodes, mut sequence_indices, jump_indices, weights, suffix_weights, _) =
+                prep_args(&tree, &weights, 0);
+            let mut all_ideals = vec![];
+            let _ = pop_jump_push_weighted(
+                num_nodes,
+                &mut sequence_indices,
+                &jump_indices,
+                &weights,
+                &suffix_weights,
+                &mut |ideal: &[usize], weight| {
+                    all_ideals.push((ideal.to_vec(), weight));
+                    ControlFlow::Continue(())
+                },
+            );
+            for budget in -1..=suffix_weights[0] + 1 {
+                let mut ideals = vec![];
+                let _ = pop_jump_push_budgeted(
+                    num_nodes,
+                    &jump_indices,
+                    &weights,
+                    &suffix_weights,
+                    budget,
+                    &mut |ideal: &[usize], weight| {
+                        ideals.push((ideal.to_vec(), weight));
+                        ControlFlow::Continue(())
+                    },
+                );
+                let expected = all_ideals
+                    .iter()
+                    .filter(|(_, weight)| *weight <= budget)
+                    .cloned()
+                    .collect::<Vec<_>>();
+                assert_eq!(ideals, expected, "budget {budget} of {tree:?}");
+            }
+        }
+    }
+}
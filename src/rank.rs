@@ -0,0 +1,268 @@
+//! # Ranking and unranking ideals in Pop Jump Push order.
+//!
+//! Pop Jump Push visits ideals in the lexicographic order of their pre-order include/exclude
+//! decisions where including a node comes first. When the decision for pre-order index `i` is
+//! reached every remaining node in `[i..n)` has an included parent, so the number of ways to
+//! complete an ideal from `i` only depends on `i`:
+//!
+//! ```text
+//! F(n) = 1
+//! F(i) = F(i + 1) + F(j[i])    (include i, or exclude i and jump over its subtree)
+//! ```
+//!
+//! The rank of an ideal is the number of ideals visited before it, which is the sum of `F(i + 1)`
+//! over every index `i` excluded along its decision path. Excluding every decision from index 0
+//! gives the empty ideal, always last with rank `F(0) - 1`, so there are `F(0) - 1` ideals. For a
+//! tree that's `F(1)` as every ideal includes the root and for a forest (a `0` root) each of the
+//! parentless nodes at the start of a jump chain from index 0 is decided independently.
+
+use std::ops::ControlFlow;
+
+use crate::node_manipulation::CountOverflowError;
+use crate::node_manipulation::Tree;
+use crate::pop_jump_push::pop_jump_push_counted;
+use crate::pop_jump_push::prep_args;
+use crate::visitor::IdealVisitor;
+
+/// Maps ideals to and from their position in the Pop Jump Push enumeration.
+pub struct IdealRanker {
+    jump_indices: Vec<usize>,
+    suffix_counts: Vec<u128>,
+    labels: Vec<usize>,
+}
+
+impl IdealRanker {
+    pub fn new(tree: &Tree) -> Result<Self, CountOverflowError> {
+        /*!  - Prepares the tree and the completion counts for each pre-order index. */
+        let (_, _, jump_indices, labels) = prep_args(tree, 0);
+        let suffix_counts = generate_suffix_counts(&jump_indices)
+            .map_err(|_| CountOverflowError { node: tree.root() })?;
+        Ok(IdealRanker {
+            jump_indices,
+            suffix_counts,
+            labels,
+        })
+    }
+
+    pub fn count(&self) -> u128 {
+        /*!  - Returns the number of (non-empty) ideals. */
+        self.suffix_counts[0] - 1
+    }
+
+    pub fn rank(&self, ideal: &[usize]) -> Option<u128> {
+        /*!  - Returns the position of ascending pre-order indices in Pop Jump Push order. */
+        // `None` when the indices aren't an ideal of the tree.
+        let num_nodes = self.jump_indices.len();
+        let mut ideal = ideal.iter().peekable();
+        let mut rank = 0;
+        let mut index = 0;
+        while index < num_nodes {
+            if ideal.peek() == Some(&&index) {
+                ideal.next();
+                index += 1;
+            } else {
+                rank += self.suffix_counts[index + 1];
+                index = self.jump_indices[index];
+            }
+        }
+        match ideal.next() {
+            // Only the empty ideal, which isn't visited, is ranked `count`.
+            None if rank < self.count() => Some(rank),
+            _ => None,
+        }
+    }
+
+    pub fn unrank(&self, rank: u128) -> Option<Vec<usize>> {
+        /*!  - Returns the ideal at the given position of the Pop Jump Push order. */
+        let mut sequence_indices = vec![];
+        self.unrank_into(rank, &mut sequence_indices)?;
+        Some(sequence_indices)
+    }
+
+    pub fn unrank_into(&self, mut rank: u128, sequence_indices: &mut Vec<usize>) -> Option<()> {
+        /*!  - Replaces sequence_indices with the ideal at the given position. */
+        // The result is a valid `pop_jump_push` stack that continues the enumeration from there.
+        if rank >= self.count() {
+            return None;
+        }
+        let num_nodes = self.jump_indices.len();
+        sequence_indices.clear();
+        let mut index = 0;
+        while index < num_nodes {
+            let included_count = self.suffix_counts[index + 1];
+            if rank < included_count {
+                sequence_indices.push(index);
+                index += 1;
+            } else {
+                rank -= included_count;
+                index = self.jump_indices[index];
+            }
+        }
+        Some(())
+    }
+
+    pub fn visit_range<V: IdealVisitor>(
+        &self,
+        start: u128,
+        end: u128,
+        visitor: &mut V,
+    ) -> ControlFlow<()> {
+        /*!  - Visits the ideals ranked `[start..end)` in Pop Jump Push order. */
+        let mut sequence_indices = Vec::with_capacity(self.jump_indices.len());
+        if start >= end || self.unrank_into(start, &mut sequence_indices).is_none() {
+            return ControlFlow::Continue(());
+        }
+        let num_nodes = self.jump_indices.len();
+        let count = end - start;
+        pop_jump_push_counted(
+            num_nodes,
+            &mut sequence_indices,
+            &self.jump_indices,
+            count,
+            visitor,
+        )
+    }
+
+    pub fn jump_indices(&self) -> &[usize] {
+        /*!  - Returns the pre-order jump indices. */
+        &self.jump_indices
+    }
+
+    pub fn suffix_counts(&self) -> &[u128] {
+        /*!  - Returns the number of ways to complete an ideal from each pre-order index. */
+        &self.suffix_counts
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        /*!  - Returns the node labels indexed by pre-order index. */
+        &self.labels
+    }
+}
+
+pub fn generate_suffix_counts(jump_indices: &[usize]) -> Result<Vec<u128>, CountOverflowError> {
+    /*!  - Returns `F(i)`, the number of ways to complete an ideal from pre-order index `i`.
+
+    `F(0)` counts the empty ideal and `F(1)` is the number of ideals containing the root. On
+    overflow the error's node is the pre-order index whose count doesn't fit.
+    */
+    let num_nodes = jump_indices.len();
+    let mut suffix_counts = vec![1u128; num_nodes + 1];
+    for index in (0..num_nodes).rev() {
+        suffix_counts[index] = suffix_counts[index + 1]
+            .checked_add(suffix_counts[jump_indices[index]])
+            .ok_or(CountOverflowError { node: index })?;
+    }
+    Ok(suffix_counts)
+}
+
+pub fn shard_range(count: u128, shard: u128, num_shards: u128) -> (u128, u128) {
+    /*!  - Returns the `[start..end)` ranks of shard `0..num_shards` of count ideals.
+
+    Shards differ in size by at most one and together cover `[0..count)` in order, so
+    concatenating the shards' output reproduces the sequential enumeration.
+    */
+    let size = count / num_shards;
+    let extra = count % num_shards;
+    let start = size * shard + std::cmp::min(shard, extra);
+    let end = start + size + u128::from(shard < extra);
+    (start, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_manipulation::Tree;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sequential_ideals;
+    use crate::test_support::sorted_labels;
+
+    #[test]
+    fn rank_and_unrank_follow_the_pop_jump_push_order() {
+        for tree in sample_trees() {
+            let ranker = IdealRanker::new(&tree).unwrap();
+            let ideals = sequential_ideals(&tree);
+            assert_eq!(ranker.count(), ideals.len() as u128, "{tree:?}");
+            for (rank, ideal) in ideals.iter().enumerate() {
+                assert_eq!(ranker.unrank(rank as u128).as_ref(), Some(ideal));
+                assert_eq!(ranker.rank(ideal), Some(rank as u128));
+            }
+            assert_eq!(ranker.unrank(ranker.count()), None);
+        }
+    }
+
+    #[test]
+    fn unranked_ideals_are_every_ideal() {
+        for tree in sample_trees() {
+            let ranker = IdealRanker::new(&tree).unwrap();
+            let ideals = (0..ranker.count())
+                .map(|rank| sorted_labels(&ranker.unrank(rank).unwrap(), ranker.labels()))
+                .collect();
+            assert_eq!(brute_force_ideals(&tree), ideals, "{tree:?}");
+        }
+    }
+
+    #[test]
+    fn non_ideals_have_no_rank() {
+        for tree in sample_trees() {
+            let ranker = IdealRanker::new(&tree).unwrap();
+            let num_nodes = tree.len();
+            assert_eq!(ranker.rank(&[]), None);
+            assert_eq!(ranker.rank(&[num_nodes]), None);
+            // The last pre-order index is a leaf whose parent, if any, comes before it.
+            let last = num_nodes - 1;
+            let has_parent = ranker.unrank(ranker.count() - 1) != Some(vec![last]);
+            if num_nodes > 1 && has_parent {
+                assert_eq!(ranker.rank(&[last]), None, "{tree:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn shards_cover_every_rank_in_order() {
+        for count in [0, 1, 5, 7, 100, u128::MAX] {
+            for num_shards in [1, 2, 3, 8, 200] {
+                let ranges = (0..num_shards)
+                    .map(|shard| shard_range(count, shard, num_shards))
+                    .collect::<Vec<_>>();
+                assert_eq!(ranges[0].0, 0);
+                assert_eq!(ranges[ranges.len() - 1].1, count);
+                for pair in ranges.windows(2) {
+                    assert_eq!(pair[0].1, pair[1].0);
+                }
+                let sizes = ranges.iter().map(|(start, end)| end - start);
+                let smallest = sizes.clone().min().unwrap();
+                assert!(
+                    sizes.max().unwrap() - smallest <= 1,
+                    "{count} in {num_shards}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn visited_shards_concatenate_to_the_sequential_order() {
+        for tree in sample_trees() {
+            let ranker = IdealRanker::new(&tree).unwrap();
+            for num_shards in [1, 3, 8] {
+                let mut ideals = vec![];
+                for shard in 0..num_shards {
+                    let (start, end) = shard_range(ranker.count(), shard, num_shards);
+                    let _ = ranker.visit_range(start, end, &mut |ideal: &[usize]| {
+                        ideals.push(ideal.to_vec());
+                        ControlFlow::Continue(())
+                    });
+                }
+                assert_eq!(ideals, sequential_ideals(&tree), "{tree:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn overflowing_counts_are_an_error() {
+        // The root of 129 leaves has 2^128 ideals.
+        let parents = std::iter::once(0).chain(vec![1; 129]).collect();
+        let tree = Tree::new(1, parents, (1..=130).collect()).unwrap();
+        assert!(IdealRanker::new(&tree).is_err());
+    }
+}
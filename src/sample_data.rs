@@ -11,6 +11,10 @@ pub(crate) const SET_7README_CHILDREN: [usize; 7] = [1, 2, 3, 4, 5, 6, 7];
 pub(crate) const SET_RUSKEY_PARENTS: [usize; 8] = [0, 1, 2, 1, 4, 4, 6, 6];
 pub(crate) const SET_RUSKEY_CHILDREN: [usize; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
 
+// A forest of three trees, so its root is `0`.
+pub(crate) const SET_7FOREST_PARENTS: [usize; 7] = [0, 1, 1, 0, 4, 0, 6];
+pub(crate) const SET_7FOREST_CHILDREN: [usize; 7] = [1, 2, 3, 4, 5, 6, 7];
+
 pub(crate) const SET_13M_PARENTS: [usize; 13] = [0, 1, 2, 3, 4, 5, 5, 1, 8, 9, 8, 11, 11];
 pub(crate) const SET_13M_CHILDREN: [usize; 13] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13];
 
@@ -83,8 +87,9 @@ pub(crate) const SET_63B_CHILDREN: [usize; 63] = [
     51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63,
 ];
 
-pub(crate) fn get_sample_data(sample_size: &str) -> (usize, Vec<usize>, Vec<usize>) {
-    let root = 1;
+pub fn get_sample_data(sample_size: &str) -> Option<(usize, Vec<usize>, Vec<usize>)> {
+    /*!  - Returns the `root`, `parents` and `children` of a named sample set, if there is one. */
+    let mut root = 1;
     let mut parents = vec![];
     let mut children = vec![];
 
@@ -94,6 +99,10 @@ pub(crate) fn get_sample_data(sample_size: &str) -> (usize, Vec<usize>, Vec<usiz
     } else if sample_size == "set_Ruskey" {
         parents = SET_RUSKEY_PARENTS.to_vec();
         children = SET_RUSKEY_CHILDREN.to_vec();
+    } else if sample_size == "set_7Forest" {
+        root = 0;
+        parents = SET_7FOREST_PARENTS.to_vec();
+        children = SET_7FOREST_CHILDREN.to_vec();
     } else if sample_size == "set_13M" {
         parents = SET_13M_PARENTS.to_vec();
         children = SET_13M_CHILDREN.to_vec();
@@ -139,8 +148,8 @@ pub(crate) fn get_sample_data(sample_size: &str) -> (usize, Vec<usize>, Vec<usiz
     }
 
     if parents.is_empty() || children.is_empty() {
-        panic!("Sample data {sample_size:?} not found.");
+        return None;
     }
 
-    (root, parents, children)
+    Some((root, parents, children))
 }
@@ -0,0 +1,213 @@
+//! # Uniform random sampling of ideals.
+//!
+//! The number of ideals rooted at a node is the product of `count_subtrees_at` over its children
+//! (each count includes the child's empty subtree) so a uniformly random ideal is built by
+//! excluding each child of an included node, independently, with probability
+//! `1 / count_subtrees_at(child)`. Walking the pre-order with the jump indices gives the sample in
+//! the same ascending pre-order indices visited by `pop_jump_push`.
+//!
+//! The parentless nodes of a forest (a `0` root) are excluded the same way and a sample that
+//! excludes all of them, the empty ideal, is redrawn.
+
+use std::ops::ControlFlow;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::node_manipulation::arrange_by_traversal_pre_order;
+use crate::node_manipulation::count_subtrees_at_each;
+use crate::node_manipulation::CountOverflowError;
+use crate::node_manipulation::Tree;
+use crate::pop_jump_push::generate_jump_indices;
+use crate::pop_jump_push::OutputVisitor;
+use crate::timing::time_runs;
+use crate::visitor::IdealVisitor;
+
+/// Draws ideals uniformly at random.
+pub struct IdealSampler {
+    jump_indices: Vec<usize>,
+    subtree_counts: Vec<u128>,
+    labels: Vec<usize>,
+    rng: StdRng,
+}
+
+impl IdealSampler {
+    pub fn new(tree: &Tree, seed: Option<u64>) -> Result<Self, CountOverflowError> {
+        /*!  - Prepares the per-subtree counts, seeding the rng when a seed is given. */
+        let (parents, children) =
+            arrange_by_traversal_pre_order(tree.root(), tree.parents(), tree.children());
+        let jump_indices = generate_jump_indices(&parents, &children);
+        // A tree's root is always included so its count, which may overflow, isn't needed.
+        let always_included = usize::from(tree.root() != 0);
+        let subtree_counts = std::iter::repeat_n(Ok(0), always_included)
+            .chain(
+                count_subtrees_at_each(&parents, &children)
+                    .into_iter()
+                    .skip(always_included),
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Ok(IdealSampler {
+            jump_indices,
+            subtree_counts,
+            labels: children,
+            rng,
+        })
+    }
+
+    pub fn sample(&mut self) -> Vec<usize> {
+        /*!  - Returns a uniformly random ideal as ascending pre-order indices. */
+        let mut sequence_indices = vec![];
+        self.sample_into(&mut sequence_indices);
+        sequence_indices
+    }
+
+    pub fn sample_into(&mut self, sequence_indices: &mut Vec<usize>) {
+        /*!  - Replaces sequence_indices with a uniformly random ideal. */
+        let num_nodes = self.jump_indices.len();
+        sequence_indices.clear();
+        while sequence_indices.is_empty() {
+            let mut index = 0;
+            while index < num_nodes {
+                let count = self.subtree_counts[index];
+                if count != 0 && self.rng.gen_range(0..count) == 0 {
+                    index = self.jump_indices[index];
+                } else {
+                    sequence_indices.push(index);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    pub fn jump_indices(&self) -> &[usize] {
+        /*!  - Returns the pre-order jump indices. */
+        &self.jump_indices
+    }
+
+    pub fn subtree_counts(&self) -> &[u128] {
+        /*!  - Returns count_subtrees_at for each pre-order index (0 for a tree's root). */
+        &self.subtree_counts
+    }
+
+    pub fn labels(&self) -> &[usize] {
+        /*!  - Returns the node labels indexed by pre-order index. */
+        &self.labels
+    }
+}
+
+pub fn sample_ideals<V: IdealVisitor>(
+    sampler: &mut IdealSampler,
+    num_samples: usize,
+    visitor: &mut V,
+) -> ControlFlow<()> {
+    /*!  - Visits num_samples uniformly random ideals (with replacement). */
+    let mut sequence_indices = Vec::with_capacity(sampler.jump_indices.len());
+    for _ in 0..num_samples {
+        sampler.sample_into(&mut sequence_indices);
+        visitor.visit(&sequence_indices)?;
+    }
+    ControlFlow::Continue(())
+}
+
+#[doc(hidden)]
+pub fn sample_ideals_main(
+    tree: &Tree,
+    num_samples: usize,
+    seed: Option<u64>,
+    output: u8,
+    reps: u32,
+) -> Result<(), CountOverflowError> {
+    /*! Draws the samples 'reps' times, visiting each one. */
+    let num_nodes = tree.len();
+    let ttl_samples = num_samples as f64 * reps as f64;
+    println!(
+        "Sampling {num_samples} ideals from {num_nodes} nodes {reps} times ({ttl_samples}).\n"
+    );
+
+    let mut sampler = IdealSampler::new(tree, seed)?;
+    let labels = sampler.labels.clone();
+
+    let timing = time_runs(
+        reps,
+        || OutputVisitor {
+            labels: &labels,
+            output,
+        },
+        |mut visitor| {
+            let _ = sample_ideals(&mut sampler, num_samples, &mut visitor);
+        },
+    );
+
+    // The samples aren't a whole tree's ideals so the durations are reported per rep.
+    let avg = timing.total / reps as f64;
+    println!("\tCompleted sampling ideals...");
+    println!("\tAvg Duration per rep {avg}");
+    println!("\tBest Duration per rep {}", timing.best);
+    println!("\t{} ns avg per sample", avg / num_samples as f64 * 1e9);
+    println!(
+        "\t{} ns best per sample\n",
+        timing.best / num_samples as f64 * 1e9
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_support::brute_force_ideals;
+    use crate::test_support::sample_trees;
+    use crate::test_support::sorted_labels;
+
+    #[test]
+    fn samples_are_ideals() {
+        for tree in sample_trees() {
+            let ideals = brute_force_ideals(&tree);
+            let mut sampler = IdealSampler::new(&tree, Some(0)).unwrap();
+            for _ in 0..100 {
+                let sample = sorted_labels(&sampler.sample(), sampler.labels());
+                assert!(ideals.contains(&sample), "{sample:?} of {tree:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_uniform() {
+        for tree in sample_trees() {
+            let ideals = brute_force_ideals(&tree);
+            if ideals.len() > 16 {
+                continue;
+            }
+            let samples_per_ideal = 1000;
+            let mut sampler = IdealSampler::new(&tree, Some(1)).unwrap();
+            let mut frequencies = HashMap::new();
+            for _ in 0..ideals.len() * samples_per_ideal {
+                let sample = sorted_labels(&sampler.sample(), sampler.labels());
+                *frequencies.entry(sample).or_insert(0usize) += 1;
+            }
+            assert_eq!(frequencies.len(), ideals.len(), "{tree:?}");
+            for (ideal, frequency) in frequencies {
+                assert!(
+                    frequency.abs_diff(samples_per_ideal) < samples_per_ideal / 4,
+                    "{ideal:?} sampled {frequency} times from {tree:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_samplers_repeat() {
+        for tree in sample_trees() {
+            let mut first = IdealSampler::new(&tree, Some(2)).unwrap();
+            let mut second = IdealSampler::new(&tree, Some(2)).unwrap();
+            for _ in 0..20 {
+                assert_eq!(first.sample(), second.sample());
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! # Small trees and brute force enumerations for checking the generators in tests.
+
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::node_manipulation::Tree;
+use crate::pop_jump_push::pop_jump_push;
+use crate::pop_jump_push::prep_args;
+use crate::sample_data::get_sample_data;
+
+/// The sample sets small enough to check against every subset of their nodes.
+const SMALL_SAMPLE_SETS: [&str; 10] = [
+    "set_7Readme",
+    "set_Ruskey",
+    "set_7Forest",
+    "set_13M",
+    "set_3B",
+    "set_3W",
+    "set_7B",
+    "set_7D",
+    "set_7W",
+    "set_15B",
+];
+
+pub(crate) fn sample_trees() -> Vec<Tree> {
+    /*!  - Returns the small sample sets followed by random trees and forests of 1 to 10 nodes. */
+    let mut trees = SMALL_SAMPLE_SETS
+        .iter()
+        .map(|name| {
+            let (root, parents, children) = get_sample_data(name).unwrap();
+            Tree::new(root, parents, children).unwrap()
+        })
+        .collect::<Vec<_>>();
+    let mut rng = StdRng::seed_from_u64(0);
+    for num_nodes in 1..=10 {
+        for forest in [false, true] {
+            for _ in 0..4 {
+                trees.push(random_tree(&mut rng, num_nodes, forest));
+            }
+        }
+    }
+    trees
+}
+
+pub(crate) fn random_tree(rng: &mut StdRng, num_nodes: usize, forest: bool) -> Tree {
+    /*!  - Returns a random tree, or forest with a `0` root, with shuffled labels and edges. */
+    let mut labels = (1..=num_nodes).collect::<Vec<_>>();
+    labels.shuffle(rng);
+    let mut edges = (0..num_nodes)
+        .map(|index| {
+            let parent = match index {
+                0 => 0,
+                _ if forest && rng.gen_bool(0.3) => 0,
+                _ => labels[rng.gen_range(0..index)],
+            };
+            (parent, labels[index])
+        })
+        .collect::<Vec<_>>();
+    edges.shuffle(rng);
+    let root = if forest { 0 } else { labels[0] };
+    let (parents, children) = edges.into_iter().unzip();
+    Tree::new(root, parents, children).unwrap()
+}
+
+pub(crate) fn brute_force_ideals(tree: &Tree) -> BTreeSet<Vec<usize>> {
+    /*!  - Returns every non-empty ideal as sorted labels by checking each subset of the nodes. */
+    let parent_of = tree
+        .children()
+        .iter()
+        .cloned()
+        .zip(tree.parents().iter().cloned())
+        .collect::<HashMap<_, _>>();
+    let num_nodes = tree.len();
+    (1..1u64 << num_nodes)
+        .filter_map(|subset| {
+            let mut nodes = (0..num_nodes)
+                .filter(|i| subset & (1 << i) != 0)
+                .map(|i| tree.children()[i])
+                .collect::<Vec<_>>();
+            nodes.sort();
+            let closed = nodes
+                .iter()
+                .all(|node| parent_of[node] == 0 || nodes.binary_search(&parent_of[node]).is_ok());
+            closed.then_some(nodes)
+        })
+        .collect()
+}
+
+pub(crate) fn sorted_labels(ideal: &[usize], labels: &[usize]) -> Vec<usize> {
+    /*!  - Returns the labels of the pre-order indices in ascending order. */
+    let mut result = ideal.iter().map(|&i| labels[i]).collect::<Vec<_>>();
+    result.sort();
+    result
+}
+
+pub(crate) fn replay_delta(ideal: &mut BTreeSet<usize>, line: &str) {
+    /*!  - Applies a `DeltaVisitor` line of `-label` and `+label` changes to the ideal's labels. */
+    for change in line.split(' ') {
+        let (sign, label) = change.split_at(1);
+        let label = label.parse().unwrap();
+        match sign {
+            "+" => assert!(ideal.insert(label), "{label} added twice"),
+            "-" => assert!(ideal.remove(&label), "{label} removed while absent"),
+            _ => panic!("{change} isn't a change"),
+        }
+    }
+}
+
+pub(crate) fn sequential_ideals(tree: &Tree) -> Vec<Vec<usize>> {
+    /*!  - Returns the pre-order index ideals in the order `pop_jump_push` visits them. */
+    let (num_nodes, mut sequence_indices, jump_indices, _) = prep_args(tree, 0);
+    let mut ideals = vec![];
+    let _ = pop_jump_push(
+        num_nodes,
+        &mut sequence_indices,
+        &jump_indices,
+        &mut |ideal: &[usize]| {
+            ideals.push(ideal.to_vec());
+            ControlFlow::Continue(())
+        },
+    );
+    ideals
+}
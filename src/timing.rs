@@ -0,0 +1,57 @@
+//! # Timing the repeated runs of the cli drivers.
+
+use std::convert::Infallible;
+use std::time::Instant;
+
+/// The total and best durations, in seconds, of a driver's repeated runs.
+pub(crate) struct Timing {
+    pub total: f64,
+    pub best: f64,
+    pub reps: u32,
+}
+
+pub(crate) fn time_runs<T>(
+    reps: u32,
+    prepare: impl FnMut() -> T,
+    mut run: impl FnMut(T),
+) -> Timing {
+    /*!  - Runs reps times, timing only run for the best duration but prepare too in the total. */
+    let Ok(timing) = try_time_runs(reps, prepare, |state| -> Result<(), Infallible> {
+        run(state);
+        Ok(())
+    });
+    timing
+}
+
+pub(crate) fn try_time_runs<T, E>(
+    reps: u32,
+    mut prepare: impl FnMut() -> T,
+    mut run: impl FnMut(T) -> Result<(), E>,
+) -> Result<Timing, E> {
+    /*!  - Runs reps times as `time_runs` does, stopping at the first run returning an error. */
+    let start_time = Instant::now();
+    let mut best = f64::MAX;
+    for _ in 0..reps {
+        let state = prepare();
+        let run_start_time = Instant::now();
+        run(state)?;
+        best = best.min(run_start_time.elapsed().as_secs_f64());
+    }
+    Ok(Timing {
+        total: start_time.elapsed().as_secs_f64(),
+        best,
+        reps,
+    })
+}
+
+impl Timing {
+    pub(crate) fn report(&self, count: u128, items: &str, item: &str) {
+        /*!  - Prints the average and best durations per run and per item of the count items. */
+        let avg = self.total / self.reps as f64;
+        println!("\tCompleted generating {items}...");
+        println!("\tAvg Duration per tree {avg}");
+        println!("\tBest Duration per tree {}", self.best);
+        println!("\t{} ns avg per {item}", avg / count as f64 * 1e9);
+        println!("\t{} ns best per {item}\n", self.best / count as f64 * 1e9);
+    }
+}
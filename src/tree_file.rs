@@ -0,0 +1,300 @@
+//! # Loading trees from files.
+//!
+//! Two formats are understood:
+//! - A JSON object `{"root": 1, "parents": [0, 1, 1], "children": [1, 2, 3]}` using the same
+//!   conventions as `sample_data` (the root's parent is `0`). An optional `weights` array gives
+//!   the integer weight of each child.
+//! - A parent/child edge list with one `parent child` pair per line separated by whitespace or a
+//!   comma. Blank lines and lines starting with `#` are ignored. The root is the only parent that
+//!   is never a child; it may also be given explicitly with a `0 root` line. An optional third
+//!   column gives the integer weight of the child, in which case every line needs one and the
+//!   root must be declared with a `0 root weight` line.
+//!
+//! Node labels must be non-zero since `0` marks the root's missing parent. A forest has several
+//! parentless nodes and is returned with a root of `0` (a JSON `root` of `0` or an edge list
+//! declaring several `0 root` edges). Either way the tree is checked by `Tree::new`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::node_manipulation::Tree;
+use crate::node_manipulation::TreeError;
+
+#[derive(Debug)]
+pub enum TreeFileError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Parse { line: usize, text: String },
+    MissingWeight(usize),
+    WeightCount { weights: usize, children: usize },
+    Tree(TreeError),
+}
+
+impl fmt::Display for TreeFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeFileError::Io(e) => write!(f, "unable to read tree file: {e}"),
+            TreeFileError::Json(e) => write!(f, "invalid tree json: {e}"),
+            TreeFileError::Parse { line, text } => {
+                write!(
+                    f,
+                    "line {line}: expected `parent child [weight]` but found {text:?}"
+                )
+            }
+            TreeFileError::MissingWeight(c) => write!(
+                f,
+                "node {c} has no weight; every node needs one when weights are given"
+            ),
+            TreeFileError::WeightCount { weights, children } => write!(
+                f,
+                "{weights} weights given for {children} children; the lengths must match"
+            ),
+            TreeFileError::Tree(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TreeFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TreeFileError::Io(e) => Some(e),
+            TreeFileError::Json(e) => Some(e),
+            TreeFileError::Tree(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TreeFileError {
+    fn from(e: std::io::Error) -> Self {
+        TreeFileError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TreeFileError {
+    fn from(e: serde_json::Error) -> Self {
+        TreeFileError::Json(e)
+    }
+}
+
+impl From<TreeError> for TreeFileError {
+    fn from(e: TreeError) -> Self {
+        TreeFileError::Tree(e)
+    }
+}
+
+/// The tree and the optional weights in the same order as its children.
+pub type WeightedTree = (Tree, Option<Vec<i64>>);
+
+#[derive(Deserialize)]
+struct JsonTree {
+    root: usize,
+    parents: Vec<usize>,
+    children: Vec<usize>,
+    weights: Option<Vec<i64>>,
+}
+
+pub fn read_tree_file(path: &Path) -> Result<Tree, TreeFileError> {
+    /*!  - Returns the tree read from a json or edge list file. */
+    let (tree, _) = read_weighted_tree_file(path)?;
+    Ok(tree)
+}
+
+pub fn read_weighted_tree_file(path: &Path) -> Result<WeightedTree, TreeFileError> {
+    /*!  - Returns the tree and weights read from a json or edge list file. */
+    let text = fs::read_to_string(path)?;
+    let is_json =
+        path.extension().is_some_and(|e| e == "json") || text.trim_start().starts_with('{');
+    if is_json {
+        parse_json_tree(&text)
+    } else {
+        parse_edge_list(&text)
+    }
+}
+
+pub fn parse_json_tree(text: &str) -> Result<WeightedTree, TreeFileError> {
+    /*!  - Returns the tree of a `{root, parents, children, weights}` object. */
+    let json: JsonTree = serde_json::from_str(text)?;
+    let tree = Tree::new(json.root, json.parents, json.children)?;
+    if let Some(weights) = &json.weights {
+        if weights.len() != tree.len() {
+            return Err(TreeFileError::WeightCount {
+                weights: weights.len(),
+                children: tree.len(),
+            });
+        }
+    }
+    Ok((tree, json.weights))
+}
+
+pub fn parse_edge_list(text: &str) -> Result<WeightedTree, TreeFileError> {
+    /*!  - Returns the tree of a `parent child [weight]` per line edge list. */
+    let mut parents = vec![];
+    let mut children = vec![];
+    let mut weights = vec![];
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        let label = |f: &str| f.parse::<usize>().ok();
+        let edge = match fields.as_slice() {
+            [parent, child] => Some((label(parent), label(child), None)),
+            [parent, child, weight] => weight
+                .parse::<i64>()
+                .ok()
+                .map(|w| (label(parent), label(child), Some(w))),
+            _ => None,
+        };
+        match edge {
+            Some((Some(parent), Some(child), weight)) => {
+                parents.push(parent);
+                children.push(child);
+                weights.push(weight);
+            }
+            _ => {
+                return Err(TreeFileError::Parse {
+                    line: line_index + 1,
+                    text: line.to_string(),
+                })
+            }
+        }
+    }
+
+    // Roots are either declared with a `0 root` edge or are the parents that aren't children.
+    let child_set: HashSet<usize> = children.iter().cloned().collect();
+    let mut roots = vec![];
+    let mut root_set = HashSet::new();
+    for &p in parents.iter() {
+        if p != 0 && !child_set.contains(&p) && root_set.insert(p) {
+            roots.push(p);
+        }
+    }
+    let declared = parents
+        .iter()
+        .zip(children.iter())
+        .filter(|(&p, _)| p == 0)
+        .map(|(_, &c)| c)
+        .collect::<Vec<_>>();
+    // Only explicitly declared roots make a forest; an undeclared one is more likely a typo.
+    let root = match (declared.as_slice(), roots.as_slice()) {
+        ([], []) if children.is_empty() => return Err(TreeError::Empty.into()),
+        ([], []) => return Err(TreeError::NoRoot.into()),
+        ([root], []) | ([], [root]) => *root,
+        (_, []) => 0,
+        _ => {
+            let roots = declared.iter().chain(roots.iter()).cloned().collect();
+            return Err(TreeError::MultipleRoots(roots).into());
+        }
+    };
+    if let [r] = roots[..] {
+        parents = std::iter::once(0).chain(parents).collect();
+        children = std::iter::once(r).chain(children).collect();
+        weights = std::iter::once(None).chain(weights).collect();
+    }
+
+    let tree = Tree::new(root, parents, children)?;
+    let weights = if weights.iter().all(|w| w.is_none()) {
+        None
+    } else {
+        let missing = weights.iter().position(|w| w.is_none());
+        if let Some(index) = missing {
+            return Err(TreeFileError::MissingWeight(tree.children()[index]));
+        }
+        Some(weights.into_iter().flatten().collect())
+    };
+    Ok((tree, weights))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_error(text: &str) -> Option<TreeError> {
+        match parse_edge_list(text) {
+            Err(TreeFileError::Tree(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn edge_lists_find_their_root() {
+        let (tree, weights) = parse_edge_list("1 2\n1 3\n").unwrap();
+        assert_eq!((tree.root(), tree.children()), (1, &[1, 2, 3][..]));
+        assert_eq!(weights, None);
+        let (tree, _) = parse_edge_list("0 1\n1 2\n").unwrap();
+        assert_eq!((tree.root(), tree.children()), (1, &[1, 2][..]));
+    }
+
+    #[test]
+    fn only_declared_roots_make_a_forest() {
+        let (tree, _) = parse_edge_list("0 1\n0 2\n1 3\n").unwrap();
+        assert_eq!(tree.root(), 0);
+        let roots = TreeError::MultipleRoots(vec![1, 4]);
+        assert_eq!(tree_error("1 2\n4 3\n"), Some(roots));
+        let roots = TreeError::MultipleRoots(vec![1, 5]);
+        assert_eq!(tree_error("0 1\n1 2\n5 3\n"), Some(roots));
+    }
+
+    #[test]
+    fn invalid_edge_lists_report_why() {
+        assert_eq!(tree_error("# nothing\n"), Some(TreeError::Empty));
+        assert_eq!(tree_error("1 2\n2 1\n"), Some(TreeError::NoRoot));
+        assert!(matches!(
+            parse_edge_list("1 2\n1 x\n"),
+            Err(TreeFileError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse_edge_list("1 2 5\n"),
+            Err(TreeFileError::MissingWeight(1))
+        ));
+    }
+
+    #[test]
+    fn json_trees_keep_their_weights() {
+        let text = r#"{"root": 1, "parents": [0, 1, 1], "children": [1, 2, 3]}"#;
+        let (tree, weights) = parse_json_tree(text).unwrap();
+        assert_eq!((tree.root(), tree.children()), (1, &[1, 2, 3][..]));
+        assert_eq!(weights, None);
+        let text = r#"{"root": 1, "parents": [0, 1], "children": [1, 2], "weights": [4, -1]}"#;
+        let (_, weights) = parse_json_tree(text).unwrap();
+        assert_eq!(weights, Some(vec![4, -1]));
+    }
+
+    #[test]
+    fn invalid_json_trees_report_why() {
+        assert!(matches!(
+            parse_json_tree(r#"{"root": 1, "parents": [0, 1"#),
+            Err(TreeFileError::Json(_))
+        ));
+        assert!(matches!(
+            parse_json_tree(r#"{"root": 1, "parents": [0, 1]}"#),
+            Err(TreeFileError::Json(_))
+        ));
+        let mismatch = TreeError::LengthMismatch {
+            parents: 3,
+            children: 2,
+        };
+        assert!(matches!(
+            parse_json_tree(r#"{"root": 1, "parents": [0, 1, 1], "children": [1, 2]}"#),
+            Err(TreeFileError::Tree(e)) if e == mismatch
+        ));
+        assert!(matches!(
+            parse_json_tree(
+                r#"{"root": 1, "parents": [0, 1], "children": [1, 2], "weights": [4]}"#
+            ),
+            Err(TreeFileError::WeightCount {
+                weights: 1,
+                children: 2
+            })
+        ));
+    }
+}
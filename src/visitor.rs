@@ -0,0 +1,139 @@
+//! # Visitors receiving the ideals produced by the generators.
+//!
+//! Every generator calls `IdealVisitor::visit` once per ideal and stops as soon as the visitor
+//! returns `ControlFlow::Break`. The ideal is passed in the generator's native representation:
+//! - `pop_jump_push` and `pop_jump_push_par` pass pre-order indices (`[usize]` or another
+//!   `NodeIndex`).
+//! - `pop_jump_push_complement` and `pop_jump_push_filters` pass ascending, non-adjacent ranges
+//!   of pre-order indices (`[Range<usize>]`), each a run of whole subtrees.
+//! - `pop_jump_push_mask` passes a `u64` or `u128` mask (an `IdealMask`) with bit `i` set for
+//!   pre-order index `i`.
+//! - `koda_ruskey` passes its `active_nodes` flags (`[u8]`) where index 0 is a sentinel and
+//!   index `i` is the state of post-order node `i - 1`.
+//!
+//! `pop_jump_push_incremental` also tells an `IncrementalIdealVisitor` which pre-order indices
+//! each step pops and pushes so aggregates over the ideal can be kept up to date without
+//! re-scanning it. Similarly `koda_ruskey_toggles` tells a `ToggleIdealVisitor` which node, the
+//! only one, each Gray code step toggled.
+//!
+//! `LabelledVisitor` translates either of the index representations into the nodes' labels,
+//! such as those of a `LabelledTree`.
+
+use std::ops::ControlFlow;
+use std::ops::Range;
+
+pub trait IdealVisitor<Ideal: ?Sized = [usize]> {
+    /// Process a single ideal, returning `ControlFlow::Break(())` to stop the enumeration.
+    fn visit(&mut self, ideal: &Ideal) -> ControlFlow<()>;
+}
+
+impl<Ideal: ?Sized, F> IdealVisitor<Ideal> for F
+where
+    F: FnMut(&Ideal) -> ControlFlow<()>,
+{
+    #[inline(always)]
+    fn visit(&mut self, ideal: &Ideal) -> ControlFlow<()> {
+        self(ideal)
+    }
+}
+
+pub trait WeightedIdealVisitor<W> {
+    /// Process a single ideal and its total weight, returning `ControlFlow::Break(())` to stop
+    /// the enumeration.
+    fn visit(&mut self, ideal: &[usize], weight: W) -> ControlFlow<()>;
+}
+
+impl<W, F> WeightedIdealVisitor<W> for F
+where
+    F: FnMut(&[usize], W) -> ControlFlow<()>,
+{
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[usize], weight: W) -> ControlFlow<()> {
+        self(ideal, weight)
+    }
+}
+
+pub trait IncrementalIdealVisitor {
+    /// Remove a pre-order index from the ideal; it is always the last index of the ideal.
+    fn pop(&mut self, index: usize);
+
+    /// Append the consecutive pre-order indices of range to the ideal.
+    fn push_range(&mut self, range: Range<usize>);
+
+    /// Process the ideal once the preceding pops and pushes have been applied, returning
+    /// `ControlFlow::Break(())` to stop the enumeration.
+    fn visit(&mut self, ideal: &[usize]) -> ControlFlow<()>;
+}
+
+pub trait ToggleIdealVisitor {
+    /// Process the `active_nodes` of an ideal that differs from the previous one only at index
+    /// `toggled`, returning `ControlFlow::Break(())` to stop the enumeration.
+    fn visit(&mut self, ideal: &[u8], toggled: usize) -> ControlFlow<()>;
+}
+
+/// Passes each ideal on to `visitor` as the labels of its nodes.
+///
+/// `labels` is indexed like the generator's nodes, e.g. `LabelledTree::labels_of` the pre-order
+/// `labels` for `pop_jump_push` or the post-order `labels` for `koda_ruskey`.
+pub struct LabelledVisitor<'a, T, V> {
+    pub labels: &'a [T],
+    pub visitor: V,
+    buffer: Vec<T>,
+}
+
+impl<'a, T, V> LabelledVisitor<'a, T, V> {
+    pub fn new(labels: &'a [T], visitor: V) -> Self {
+        /*!  - Returns the visitor reusing one label buffer for every ideal. */
+        LabelledVisitor {
+            labels,
+            visitor,
+            buffer: Vec::with_capacity(labels.len()),
+        }
+    }
+}
+
+impl<T: Clone, V: IdealVisitor<[T]>> IdealVisitor for LabelledVisitor<'_, T, V> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[usize]) -> ControlFlow<()> {
+        self.buffer.clear();
+        self.buffer
+            .extend(ideal.iter().map(|&i| self.labels[i].clone()));
+        self.visitor.visit(&self.buffer)
+    }
+}
+
+impl<T: Clone, V: IdealVisitor<[T]>> IdealVisitor<[u8]> for LabelledVisitor<'_, T, V> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[u8]) -> ControlFlow<()> {
+        // Skip the sentinel so the flags line up with the post-order labels.
+        self.buffer.clear();
+        let active = ideal[1..].iter().zip(self.labels).filter(|(&a, _)| a == 1);
+        self.buffer.extend(active.map(|(_, label)| label.clone()));
+        self.visitor.visit(&self.buffer)
+    }
+}
+
+/// Counts the visited ideals without otherwise inspecting them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdealCounter {
+    pub count: usize,
+}
+
+impl<Ideal: ?Sized> IdealVisitor<Ideal> for IdealCounter {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &Ideal) -> ControlFlow<()> {
+        // This just ensures the compiler doesn't optimize anything away during benchmarking.
+        std::hint::black_box(ideal);
+        self.count += 1;
+        ControlFlow::Continue(())
+    }
+}
+
+impl<W> WeightedIdealVisitor<W> for IdealCounter {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &[usize], weight: W) -> ControlFlow<()> {
+        std::hint::black_box((ideal, weight));
+        self.count += 1;
+        ControlFlow::Continue(())
+    }
+}
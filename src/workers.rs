@@ -0,0 +1,84 @@
+//! # Chunk assignment and output shared by the parallel generators.
+//!
+//! The parallel Pop Jump Push and Koda-Ruskey generators both cut the ideals into about
+//! `CHUNKS_PER_WORKER` chunks per worker, each with a predicted ideal count, then balance the
+//! workers by those counts and print each ideal prefixed by the worker that visited it.
+
+use std::ops::ControlFlow;
+use std::time::Duration;
+
+use crate::visitor::IdealVisitor;
+
+/// The number of chunks to aim for per worker so the chunks can be balanced.
+pub(crate) const CHUNKS_PER_WORKER: usize = 8;
+
+pub(crate) fn assign_workers(predicted_counts: &[u128], num_workers: usize) -> Vec<usize> {
+    /*!  - Returns each chunk's worker, handing out the chunks largest first to the worker with
+    the fewest predicted ideals.
+    */
+    let mut by_size = (0..predicted_counts.len()).collect::<Vec<_>>();
+    by_size.sort_by_key(|&i| std::cmp::Reverse(predicted_counts[i]));
+    let mut worker_ids = vec![0; predicted_counts.len()];
+    let mut worker_totals = vec![0u128; std::cmp::max(num_workers, 1)];
+    for i in by_size {
+        let (worker_id, total) = worker_totals
+            .iter_mut()
+            .enumerate()
+            .min_by_key(|(_, total)| **total)
+            .unwrap();
+        *total = total.saturating_add(predicted_counts[i]);
+        worker_ids[i] = worker_id;
+    }
+    worker_ids
+}
+
+pub(crate) fn count_workers(worker_ids: impl IntoIterator<Item = usize>) -> usize {
+    /*!  - Returns the number of workers that have been assigned chunks. */
+    worker_ids.into_iter().map(|id| id + 1).max().unwrap_or(0)
+}
+
+pub(crate) fn report_workers(performance_data: &[(usize, Duration, usize, u128)]) {
+    /*!  - Prints each worker's time, ideal count and predicted count for the last rep. */
+    println!("\tWorkers summary for the last rep...");
+    for (id, delta, ideal_count, predicted_count) in performance_data.iter() {
+        let delta = delta.as_secs_f64();
+        println!(
+            "\t\tworker {id:<3} generated {ideal_count} ideals ({predicted_count} predicted) in {delta} for {:?} (ns)",
+            delta / *ideal_count as f64 * 1e9,
+        )
+    }
+    let generated_count: usize = performance_data
+        .iter()
+        .map(|(_, _, ideal_count, _)| ideal_count)
+        .sum();
+    println!("\t\tWorkers    generated {generated_count} ideals.\n");
+}
+
+/// Prints ideals prefixed by the worker id according to the cli `output` setting and counts them.
+///
+/// `format` is the generator's own text for an ideal, printed for outputs 2 and up.
+pub struct WorkerVisitor<'a, Ideal: ?Sized> {
+    pub labels: &'a [usize],
+    pub worker_id: usize,
+    pub output: u8,
+    pub count: usize,
+    pub format: fn(&Ideal, &[usize], u8) -> String,
+}
+
+impl<Ideal: ?Sized> IdealVisitor<Ideal> for WorkerVisitor<'_, Ideal> {
+    #[inline(always)]
+    fn visit(&mut self, ideal: &Ideal) -> ControlFlow<()> {
+        // This just ensures the compiler doesn't optimize anything away during `output == 0` benchmarking.
+        let ideal = std::hint::black_box(ideal);
+
+        if self.output >= 2 {
+            let worker_id = self.worker_id;
+            println!(
+                "{worker_id:<3}: {}",
+                (self.format)(ideal, self.labels, self.output)
+            );
+        }
+        self.count += 1;
+        ControlFlow::Continue(())
+    }
+}